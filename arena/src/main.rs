@@ -0,0 +1,272 @@
+//! Plays bots against each other straight on `Universe`, without a server:
+//!
+//!     cargo run --release -p arena -- --games 1000 --width 24 --height 24 medium hard
+//!
+//! The bots swap colors after every game, so the advantage of moving first
+//! cancels out over an even number of games.
+
+use common::bot::{Bot, Difficulty, MctsBot};
+use common::constants::*;
+use common::*;
+use std::thread;
+
+const USAGE: &str = "usage: arena [options] <bot> <bot>
+bots: easy, medium, hard, expert
+options:
+  --games N             games to play (default 100)
+  --seed N              seed of the first game, game i uses seed + i (default 0)
+  --width N             board width (default 32)
+  --height N            board height (default 32)
+  --neutral N           neutral blocks on random boards (default 100)
+  --map FILE            play on a map file instead of random boards
+  --rule RULE           victory rule, as in CHERRY_VICTORY_RULE
+  --neighbourhood N     von-neumann (4) or moore (8)
+  --capture             enclosed groups flip to the encloser
+  --decay A,B,C         decay mode, as in CHERRY_DECAY, needs a score: rule
+  --playouts N          fixed playouts per pick for expert bots instead of a time budget
+  --max-ticks N         score games by territory after this many ticks (default 2000)
+  --threads N           games played at the same time (default: one per core)";
+
+struct Config {
+    bots: [Difficulty; 2],
+    games: u64,
+    seed: u64,
+    width: usize,
+    height: usize,
+    neutral: u32,
+    map: Option<Universe>,
+    settings: GameSettings,
+    playouts: Option<u32>,
+    max_ticks: u32,
+    threads: usize,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
+    let mut config = Config {
+        bots: [Difficulty::Easy; 2],
+        games: 100,
+        seed: 0,
+        width: WIDTH_UNIVERSE as usize,
+        height: HEIGHT_UNIVERSE as usize,
+        neutral: N_NEUTRAL_BLOCKS,
+        map: None,
+        settings: GameSettings::default(),
+        playouts: None,
+        max_ticks: 2000,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    // every pick ends the turn, there is no one to wait for
+    config.settings.clock.pick_ends_turn = true;
+    let mut bots = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let number = |v: String| v.parse().map_err(|_| format!("bad number {}", v));
+        let size = |v: String| match v.parse() {
+            Ok(n) if n >= 1 => Ok(n),
+            _ => Err(format!("bad size {}", v)),
+        };
+        match arg.as_str() {
+            "--games" => config.games = number(value()?)?,
+            "--seed" => config.seed = number(value()?)?,
+            "--width" => config.width = size(value()?)?,
+            "--height" => config.height = size(value()?)?,
+            "--neutral" => config.neutral = number(value()?)? as u32,
+            "--map" => {
+                let path = value()?;
+                let text =
+                    std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                config.map =
+                    Some(Universe::from_map(&text).map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--rule" => config.settings.victory_rule = value()?.parse()?,
+            "--neighbourhood" => config.settings.neighbourhood = value()?.parse()?,
+            "--capture" => config.settings.capture = true,
+            "--decay" => config.settings.decay = Some(value()?.parse()?),
+            "--playouts" => config.playouts = Some(number(value()?)? as u32),
+            "--max-ticks" => config.max_ticks = number(value()?)? as u32,
+            "--threads" => config.threads = (number(value()?)? as usize).max(1),
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => bots.push(arg.parse()?),
+        }
+    }
+    match bots[..] {
+        [a, b] => config.bots = [a, b],
+        _ => return Err(USAGE.into()),
+    }
+    let (width, height) = match &config.map {
+        Some(map) => (map.width(), map.height()),
+        None => (config.width, config.height),
+    };
+    config.settings.check(width, height)?;
+    Ok(config)
+}
+
+fn make_bot(config: &Config, difficulty: Difficulty, seed: u64) -> Box<dyn Bot + Send> {
+    match (difficulty, config.playouts) {
+        (Difficulty::Expert, Some(n)) => {
+            Box::new(MctsBot::new(seed, f64::INFINITY).with_max_playouts(n))
+        }
+        _ => difficulty.bot(seed),
+    }
+}
+
+struct GameResult {
+    /// index of the bot that won, `None` for a draw
+    winner: Option<usize>,
+    red_won: bool,
+    ticks: u32,
+}
+
+fn play(config: &Config, game: u64) -> GameResult {
+    let seed = config.seed + game;
+    let board = match &config.map {
+        Some(map) => map.clone(),
+        None => Universe::generate(config.width, config.height, config.neutral, seed),
+    };
+    let mut uni = board.with_settings(config.settings.clone());
+    // the first bot plays red in even games
+    let red = (game % 2) as usize;
+    let mut bots = [
+        make_bot(config, config.bots[0], seed.wrapping_mul(2)),
+        make_bot(config, config.bots[1], seed.wrapping_mul(2) + 1),
+    ];
+    while !uni.is_finished() && uni.get_tick() < config.max_ticks {
+        let turn = uni.get_clock().turn.clone();
+        let player = if turn == Color::Red { red } else { 1 - red };
+        let picked = match bots[player].pick(&uni, &turn) {
+            Some(coords) => uni.pick(&turn, coords).unwrap_or(false),
+            None => false,
+        };
+        if !picked {
+            uni.advance_clock(config.settings.clock.turn_length);
+        }
+    }
+    let winner = match uni.get_outcome() {
+        Some(GameOutcome::Territory(c))
+        | Some(GameOutcome::Connected(c))
+        | Some(GameOutcome::Objective(c))
+        | Some(GameOutcome::Forfeit(c)) => c,
+        Some(GameOutcome::Draw) | Some(GameOutcome::Abandoned) => Color::None,
+        None => {
+            let (red, blue, _) = uni.territory();
+            if red > blue {
+                Color::Red
+            } else if blue > red {
+                Color::Blue
+            } else {
+                Color::None
+            }
+        }
+    };
+    GameResult {
+        winner: match winner {
+            Color::Red => Some(red),
+            Color::Blue => Some(1 - red),
+            Color::None => None,
+        },
+        red_won: winner == Color::Red,
+        ticks: uni.get_tick(),
+    }
+}
+
+/// 95% Wilson score interval for `wins` out of `n` games.
+fn wilson(wins: u64, n: u64) -> (f64, f64) {
+    if n == 0 {
+        return (0., 1.);
+    }
+    let z = 1.96;
+    let n = n as f64;
+    let p = wins as f64 / n;
+    let denominator = 1. + z * z / n;
+    let center = (p + z * z / (2. * n)) / denominator;
+    let half = z * (p * (1. - p) / n + z * z / (4. * n * n)).sqrt() / denominator;
+    ((center - half).max(0.), (center + half).min(1.))
+}
+
+fn print_rate(label: &str, wins: u64, games: u64) {
+    let (low, high) = wilson(wins, games);
+    println!(
+        "{label}: {wins} wins, {:.1}% (95% CI {:.1}-{:.1}%)",
+        100. * wins as f64 / games.max(1) as f64,
+        100. * low,
+        100. * high
+    );
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let threads = config.threads as u64;
+    let results: Vec<GameResult> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let config = &config;
+                scope.spawn(move || {
+                    (t..config.games)
+                        .step_by(threads as usize)
+                        .map(|game| play(config, game))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+
+    let games = results.len() as u64;
+    let wins = |bot| results.iter().filter(|r| r.winner == Some(bot)).count() as u64;
+    let draws = results.iter().filter(|r| r.winner.is_none()).count();
+    let red_wins = results.iter().filter(|r| r.red_won).count() as u64;
+    let ticks: u64 = results.iter().map(|r| r.ticks as u64).sum();
+    println!(
+        "{} games of {} vs {}, {:.1} ticks on average",
+        games,
+        config.bots[0],
+        config.bots[1],
+        ticks as f64 / games.max(1) as f64
+    );
+    print_rate(&format!("1 ({})", config.bots[0]), wins(0), games);
+    print_rate(&format!("2 ({})", config.bots[1]), wins(1), games);
+    println!("draws: {}", draws);
+    print_rate("red", red_wins, games);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn wilson_interval_contains_the_rate() {
+        assert_eq!(wilson(0, 0), (0., 1.));
+        let (low, high) = wilson(50, 100);
+        assert!(low < 0.5 && high > 0.5);
+        assert!((0.5 - low - (high - 0.5)).abs() < 1e-9);
+        assert!(high - low < 0.2);
+        let (low, high) = wilson(100, 100);
+        assert!(low > 0.9 && high > 1. - 1e-9);
+        assert_eq!(wilson(0, 100).0, 0.);
+    }
+
+    #[test]
+    fn arguments_are_checked() {
+        let config = parse_args(args("--games 10 --width 20 easy hard")).unwrap();
+        assert_eq!((config.games, config.width), (10, 20));
+        assert_eq!(config.bots, [Difficulty::Easy, Difficulty::Hard]);
+        assert!(parse_args(args("--width 0 easy hard")).is_err());
+        assert!(parse_args(args("--height 0 easy hard")).is_err());
+        assert!(parse_args(args("easy")).is_err());
+        assert!(parse_args(args("--rule objective:40,1 easy hard")).is_err());
+    }
+}
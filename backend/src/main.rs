@@ -2,21 +2,46 @@ use actix_session::{storage::CookieSessionStore, Session, SessionMiddleware};
 use actix_web::cookie::Key;
 use actix_web::error::JsonPayloadError;
 use actix_web::web::{Data, Json};
-use actix_web::{get, post, web, App, HttpResponse, HttpServer};
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web_lab::sse;
 use actix_web_lab::web::spa;
+use common::api::{BotJoin, BotJoined, PickResult};
+use common::bot::{now_ms, Bot, Difficulty};
+use common::chat::{Chat, ChatPost};
+use common::constants::*;
+use common::lobby::{
+    Challenge, ChallengeTarget, Lobby, LobbyUser, OpenRoom, PrivateGame, PrivateGameCreated,
+    RunningGame, UserStatus,
+};
 use common::*;
 use rand;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, TryLockError};
 use std::time;
 
 const USER_NAME: &str = "user_name";
 const USER_COLOR: &str = "user_color";
 const USER_UNIVERSE_IDX: &str = "universe_index";
 
+// seconds a seated player may stop polling before forfeiting the game
+const DEFAULT_GRACE_PERIOD: u64 = 15;
+
+// garbage collection of universes and users
+const REAP_INTERVAL: time::Duration = time::Duration::from_secs(30);
+const FINISHED_TTL: time::Duration = time::Duration::from_secs(60);
+const IDLE_TTL: time::Duration = time::Duration::from_secs(300);
+const USER_TTL: time::Duration = time::Duration::from_secs(300);
+
+// the lobby feed is checked for changes every LOBBY_PUSH_INTERVAL and sent
+// every LOBBY_KEEP_ALIVE even without any, so closed feeds are noticed
+const LOBBY_PUSH_INTERVAL: time::Duration = time::Duration::from_secs(1);
+const LOBBY_KEEP_ALIVE: time::Duration = time::Duration::from_secs(15);
+// lobby updates a slow client may fall behind before its feed is dropped
+const LOBBY_FEED_BUFFER: usize = 8;
+
 trait GenerateRandom {
     fn new_rand() -> Self;
 }
@@ -42,25 +67,417 @@ impl GenerateRandom for Universe {
 struct AppState {
     app_name: String,
     users: Mutex<Vec<User>>,
-    universe: Mutex<HashMap<u32, Universe>>,
-    active_user: Mutex<ActiveUser>,
+    user_seen: Mutex<HashMap<String, time::Instant>>,
+    universe: Mutex<HashMap<u32, Game>>,
+    // universe new players are seated in
     uni_id: AtomicU32,
+    next_uni_id: AtomicU32,
+    grace_period: time::Duration,
+    settings: GameSettings,
+    map: Option<Universe>,
+    reaped_universes: AtomicU32,
+    reaped_users: AtomicU32,
+    // external bots must send this key to join, if set
+    bot_key: Option<String>,
+    bot_seats: Mutex<HashMap<String, (u32, Color)>>,
+    lobby_chat: Mutex<Chat>,
+    /// open lobby feeds, with the name of the user watching
+    lobby_feed: Mutex<Vec<(Option<String>, sse::Sender)>>,
+    /// lobby last sent to the feeds, as JSON
+    last_lobby: Mutex<String>,
+    challenges: Mutex<Vec<Challenge>>,
 }
 
-struct ActiveUser {
-    time: time::Instant,
-    pub status: Color,
-    pub cell_picked: bool,
+impl AppState {
+    /// Starts a game on the configured map, or on a random board without one.
+    /// Hints are allowed if `hints` is set and the server allows them.
+    fn new_game(&self, hints: bool) -> Game {
+        let board = self.map.clone().unwrap_or_else(Universe::new_rand);
+        let mut settings = self.settings.clone();
+        settings.hints &= hints;
+        Game::new(board.with_settings(settings))
+    }
+
+    /// Seats a player in the open universe, or opens a new one when both
+    /// colors are taken there.
+    fn seat_player(&self, universes: &mut HashMap<u32, Game>) -> (u32, Color) {
+        let uidx = self.uni_id.load(Ordering::SeqCst);
+        // the open universe may have been reaped while nobody was waiting in it
+        let game = universes.entry(uidx).or_insert_with(|| self.new_game(true));
+        if !game.universe.red_player_connected {
+            game.seat(&Color::Red);
+            (uidx, Color::Red)
+        } else if !game.universe.blue_player_connected {
+            game.seat(&Color::Blue);
+            (uidx, Color::Blue)
+        } else {
+            let uidx = self.next_uni_id.fetch_add(1, Ordering::SeqCst);
+            self.uni_id.store(uidx, Ordering::SeqCst);
+            let mut game = self.new_game(true);
+            game.seat(&Color::Red);
+            universes.insert(uidx, game);
+            (uidx, Color::Red)
+        }
+    }
+
+    /// The lobby as its visitors see it.
+    fn lobby(&self) -> Lobby {
+        let mut lobby = Lobby::default();
+        let mut status = HashMap::new();
+        {
+            let universes = self.universe.lock().unwrap();
+            let mut ids: Vec<u32> = universes.keys().copied().collect();
+            ids.sort();
+            for id in ids {
+                let game = &universes[&id];
+                let uni = &game.universe;
+                if uni.is_finished() {
+                    continue;
+                }
+                let seated = uni.red_player_connected && uni.blue_player_connected;
+                for (name, color) in game.names.iter() {
+                    let user_status = match color {
+                        Color::None => UserStatus::Spectating,
+                        _ if seated => UserStatus::InGame,
+                        _ => UserStatus::InQueue,
+                    };
+                    status.insert(name.clone(), user_status);
+                }
+                // players of private games show as busy, the games
+                // themselves are not listed
+                if game.invite.is_some() {
+                    continue;
+                }
+                if seated {
+                    let (_, red, blue, _) = uni.get_cell_numbers();
+                    lobby.games.push(RunningGame {
+                        id,
+                        red: game.name_of(&Color::Red),
+                        blue: game.name_of(&Color::Blue),
+                        score: (red, blue),
+                        spectators: game.names.iter().filter(|n| n.1 == Color::None).count() as u32,
+                    });
+                } else if game.reserved_for.is_none() {
+                    if let Some((host, _)) = game.names.iter().find(|n| n.1 != Color::None) {
+                        lobby.rooms.push(OpenRoom {
+                            id,
+                            host: host.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        lobby.users = self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|user| LobbyUser {
+                name: user.name.clone(),
+                status: status.get(&user.name).copied().unwrap_or(UserStatus::Idle),
+            })
+            .collect();
+        lobby.challenges = self.challenges.lock().unwrap().clone();
+        lobby.chat = self.lobby_chat.lock().unwrap().messages();
+        lobby
+    }
+
+    /// Sends the lobby to its feeds if it changed, or anyway with
+    /// `keep_alive`. Feeds that are closed or too far behind are dropped,
+    /// the users still watching count as present.
+    fn push_lobby(&self, keep_alive: bool) {
+        let json = serde_json::to_string(&self.lobby()).unwrap();
+        {
+            let mut last = self.last_lobby.lock().unwrap();
+            if *last == json && !keep_alive {
+                return;
+            }
+            *last = json.clone();
+        }
+        let mut feed = self.lobby_feed.lock().unwrap();
+        feed.retain(|(_, tx)| tx.try_send(sse::Data::new(json.clone())).is_ok());
+        let mut seen = self.user_seen.lock().unwrap();
+        for name in feed.iter().filter_map(|(name, _)| name.clone()) {
+            seen.insert(name, time::Instant::now());
+        }
+    }
+
+    /// Universe and color of the external bot whose token the request carries.
+    fn bot_seat(&self, request: &HttpRequest) -> Option<(u32, Color)> {
+        let token = request
+            .headers()
+            .get("Authorization")?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        self.bot_seats.lock().unwrap().get(token).cloned()
+    }
+
+    /// Records that the session's user is still around.
+    fn touch_user(&self, session: &Session) {
+        if let Ok(Some(name)) = session.get::<String>(USER_NAME) {
+            self.user_seen
+                .lock()
+                .unwrap()
+                .insert(name, time::Instant::now());
+        }
+    }
+
+    /// Evicts finished or idle universes and users that stopped sending
+    /// requests. Runs periodically from a task spawned in `main`.
+    fn reap(&self) {
+        let n_universes = {
+            let mut universes = self.universe.lock().unwrap();
+            let before = universes.len();
+            universes.retain(|idx, game| {
+                game.check_heartbeats(self.grace_period);
+                let expired = game.expired();
+                if expired {
+                    println!("reaping universe {idx}: {:?}", game.universe.get_outcome());
+                }
+                !expired
+            });
+            // tokens of external bots die with their universe
+            self.bot_seats
+                .lock()
+                .unwrap()
+                .retain(|_, seat| universes.contains_key(&seat.0));
+            before - universes.len()
+        };
+        let n_users = {
+            let mut seen = self.user_seen.lock().unwrap();
+            seen.retain(|_, t| t.elapsed() < USER_TTL);
+            let mut users = self.users.lock().unwrap();
+            let before = users.len();
+            users.retain(|u| seen.contains_key(&u.name));
+            self.challenges
+                .lock()
+                .unwrap()
+                .retain(|c| seen.contains_key(&c.from) && seen.contains_key(&c.to));
+            before - users.len()
+        };
+        self.reaped_universes
+            .fetch_add(n_universes as u32, Ordering::SeqCst);
+        self.reaped_users
+            .fetch_add(n_users as u32, Ordering::SeqCst);
+        if n_universes > 0 || n_users > 0 {
+            println!("reaped {n_universes} universes and {n_users} users");
+        }
+        if n_users > 0 {
+            self.release_seats();
+        }
+    }
+
+    /// Opens seats kept for users that are gone, so whoever takes their
+    /// name next does not get them.
+    fn release_seats(&self) {
+        let names: Vec<String> = self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|u| u.name.clone())
+            .collect();
+        for game in self.universe.lock().unwrap().values_mut() {
+            if game
+                .reserved_for
+                .as_ref()
+                .is_some_and(|name| !names.contains(name))
+            {
+                game.reserved_for = None;
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Metrics {
+    universes: usize,
+    users: usize,
+    reaped_universes: u32,
+    reaped_users: u32,
+}
+
+type SharedBot = Arc<Mutex<Box<dyn Bot + Send>>>;
+
+struct Game {
+    universe: Universe,
+    last_tick: time::Instant,
+    red_seen: time::Instant,
+    blue_seen: time::Instant,
+    finished_at: Option<time::Instant>,
+    /// computer player, locked while it searches for a pick
+    bot: Option<(Color, SharedBot)>,
+    chat: Chat,
+    /// users seated in the game or watching it, spectators with
+    /// `Color::None`
+    names: Vec<(String, Color)>,
+    /// user whose accepted challenge the free seat is kept for
+    reserved_for: Option<String>,
+    /// token of the invite link of a private game, which is kept out of
+    /// the lobby
+    invite: Option<String>,
 }
 
-impl ActiveUser {
-    fn new() -> Self {
+impl Game {
+    fn new(universe: Universe) -> Self {
         Self {
-            time: time::Instant::now(),
-            status: Color::Red,
-            cell_picked: false,
+            universe,
+            last_tick: time::Instant::now(),
+            red_seen: time::Instant::now(),
+            blue_seen: time::Instant::now(),
+            finished_at: None,
+            bot: None,
+            chat: Chat::new(),
+            names: vec![],
+            reserved_for: None,
+            invite: None,
         }
     }
+
+    /// Name of the user playing `color`.
+    fn name_of(&self, color: &Color) -> String {
+        match self.names.iter().find(|n| n.1 == *color) {
+            Some((name, _)) => name.clone(),
+            None => format!("{:?}", color),
+        }
+    }
+
+    /// Seats a player as `color`. Every seat restarts the clock, so the
+    /// wait for an opponent is not charged to anyone.
+    fn seat(&mut self, color: &Color) {
+        match color {
+            Color::Red => self.universe.red_player_connected = true,
+            Color::Blue => self.universe.blue_player_connected = true,
+            Color::None => return,
+        }
+        self.heartbeat(color);
+        self.last_tick = time::Instant::now();
+    }
+
+    /// Feeds the time since the last call into the universe clock. The
+    /// clock stands still until both seats are taken.
+    fn run_clock(&mut self) {
+        let dt = self.last_tick.elapsed().as_secs_f64();
+        self.last_tick = time::Instant::now();
+        if !(self.universe.red_player_connected && self.universe.blue_player_connected) {
+            return;
+        }
+        if self.universe.advance_clock(dt) {
+            println!("evolve");
+        }
+    }
+
+    /// Hands out the seated bot and a copy of the universe once it is the
+    /// bot's turn, so it can think without holding the lock on all
+    /// universes. The bot stays seated in the game while it searches. It
+    /// counts as present whenever its opponent polls.
+    fn bot_turn(&mut self) -> Option<(Color, SharedBot, Universe)> {
+        let (color, bot) = self.bot.clone()?;
+        self.heartbeat(&color);
+        let clock = self.universe.get_clock();
+        if clock.turn != color || clock.picked || self.universe.is_finished() {
+            return None;
+        }
+        Some((color, bot, self.universe.clone()))
+    }
+
+    /// Plays the pick the bot came up with.
+    fn bot_played(&mut self, color: &Color, pick: Option<Coords>, estimate: Option<f64>) {
+        if let Some(coords) = pick {
+            let _ = self.universe.pick(color, coords);
+        }
+        self.universe.bot_estimate = estimate;
+    }
+
+    /// Ends the game for the player of `color` giving it up. The opponent
+    /// wins by forfeit if they ever sat down, otherwise the game never
+    /// started and counts as abandoned.
+    fn leave(&mut self, color: &Color) {
+        let opponent_seated = match color {
+            Color::Red => self.universe.blue_player_connected,
+            Color::Blue => self.universe.red_player_connected,
+            Color::None => return,
+        };
+        if opponent_seated {
+            self.universe.finish(GameOutcome::Forfeit(color.opponent()));
+        } else {
+            self.universe.finish(GameOutcome::Abandoned);
+        }
+    }
+
+    fn heartbeat(&mut self, color: &Color) {
+        match color {
+            Color::Red => self.red_seen = time::Instant::now(),
+            Color::Blue => self.blue_seen = time::Instant::now(),
+            Color::None => {}
+        }
+    }
+
+    /// Ends the game once a seated player has not polled for longer than
+    /// `grace`. The remaining player wins by forfeit; if nobody is left the
+    /// game counts as abandoned.
+    fn check_heartbeats(&mut self, grace: time::Duration) {
+        if self.universe.is_finished() {
+            return;
+        }
+        let red_gone = self.universe.red_player_connected && self.red_seen.elapsed() > grace;
+        let blue_gone = self.universe.blue_player_connected && self.blue_seen.elapsed() > grace;
+        if red_gone && self.universe.blue_player_connected && !blue_gone {
+            self.universe.finish(GameOutcome::Forfeit(Color::Blue));
+        } else if blue_gone && !red_gone {
+            self.universe.finish(GameOutcome::Forfeit(Color::Red));
+        } else if red_gone || blue_gone {
+            self.universe.finish(GameOutcome::Abandoned);
+        }
+    }
+
+    /// Whether the game can be dropped: finished for longer than
+    /// `FINISHED_TTL`, or nobody has polled it for `IDLE_TTL`.
+    fn expired(&mut self) -> bool {
+        if self.universe.is_finished() {
+            let finished_at = *self.finished_at.get_or_insert_with(time::Instant::now);
+            finished_at.elapsed() > FINISHED_TTL
+        } else {
+            self.red_seen.max(self.blue_seen).elapsed() > IDLE_TTL
+        }
+    }
+}
+
+fn session_color(session: &Session) -> Color {
+    match session.get::<String>(USER_COLOR) {
+        Ok(Some(c)) => Color::from_name(&c),
+        _ => Color::None,
+    }
+}
+
+fn session_universe(session: &Session) -> Option<u32> {
+    session.get::<u32>(USER_UNIVERSE_IDX).ok().flatten()
+}
+
+/// Who a chat message counts against for the rate limit: the registered
+/// name, which is unique, or the address of users without one. Unlike the
+/// session cookie neither is reset by clearing cookies.
+fn chat_sender(session: &Session, request: &HttpRequest) -> String {
+    match session_name(session) {
+        Some(name) => format!("name:{}", name),
+        None => format!(
+            "ip:{}",
+            request.connection_info().realip_remote_addr().unwrap_or("")
+        ),
+    }
+}
+
+fn session_name(session: &Session) -> Option<String> {
+    session.get::<String>(USER_NAME).ok().flatten()
+}
+
+/// Takes a user out of every game they were seated in or watching, before
+/// they sit down somewhere else.
+fn forget_user(universes: &mut HashMap<u32, Game>, name: &str) {
+    for game in universes.values_mut() {
+        game.names.retain(|n| n.0 != name);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -89,6 +506,10 @@ async fn register_user(
     let new_user: User = request.into_inner();
 
     match session.get::<String>(USER_NAME) {
+        // challenges and kept seats go by name, so names have to be unique
+        Err(_) | Ok(None) if users.iter().any(|u| u.name == new_user.name) => {
+            return Ok(HttpResponse::Conflict().body("this name is already taken"));
+        }
         Err(_) | Ok(None) => {
             users.push(new_user.clone());
             let _ = session.insert(USER_NAME, new_user.name);
@@ -98,13 +519,14 @@ async fn register_user(
         }
     }
     println!("{:?}", users);
+    drop(users);
+    app_state.touch_user(&session);
     Ok(HttpResponse::Ok().body("success creating new user"))
 }
 
 #[get("/user/color")]
 async fn give_user_color(app_state: Data<AppState>, session: Session) -> HttpResponse {
-    let color;
-    let uidx = &app_state.uni_id;
+    app_state.touch_user(&session);
     let early_return = {
         HttpResponse::Ok().content_type("application/json").body(
             serde_json::to_string(&ColorSender {
@@ -117,23 +539,16 @@ async fn give_user_color(app_state: Data<AppState>, session: Session) -> HttpRes
         Ok(v) => v,
         Err(_) => return early_return,
     };
-    let mut uni = match universe_handle.get_mut(&uidx.load(Ordering::SeqCst)) {
-        Some(v) => v,
-        None => return early_return,
-    };
-    if !uni.red_player_connected {
-        color = "red";
-        uni.red_player_connected = true;
-    } else if !uni.blue_player_connected {
-        color = "blue";
-        uni.blue_player_connected = true;
-    } else {
-        color = "red";
-        uidx.fetch_add(1, Ordering::SeqCst);
-        let mut uni = Universe::new_rand();
-        uni.red_player_connected = true;
-        universe_handle.insert(uidx.load(Ordering::SeqCst), uni);
+    let (uidx, color) = app_state.seat_player(&mut universe_handle);
+    if let Some(name) = session_name(&session) {
+        forget_user(&mut universe_handle, &name);
+        let game = universe_handle.get_mut(&uidx).unwrap();
+        game.names.push((name, color.clone()));
     }
+    let color = match color {
+        Color::Blue => "blue",
+        _ => "red",
+    };
     let _ = session.insert(USER_COLOR, color);
     let _ = session.insert(USER_UNIVERSE_IDX, uidx);
     HttpResponse::Ok().content_type("application/json").body(
@@ -144,8 +559,138 @@ async fn give_user_color(app_state: Data<AppState>, session: Session) -> HttpRes
     )
 }
 
+/// Seats the user as red in a new universe of their own, with a bot of the
+/// given difficulty playing blue.
+#[get("/user/bot/{difficulty}")]
+async fn seat_bot(
+    app_state: Data<AppState>,
+    session: Session,
+    difficulty: web::Path<String>,
+) -> HttpResponse {
+    let difficulty: Difficulty = match difficulty.parse() {
+        Ok(v) => v,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    app_state.touch_user(&session);
+    let uidx = app_state.next_uni_id.fetch_add(1, Ordering::SeqCst);
+    let mut game = app_state.new_game(true);
+    game.seat(&Color::Red);
+    game.seat(&Color::Blue);
+    game.bot = Some((
+        Color::Blue,
+        Arc::new(Mutex::new(difficulty.bot(rand::random()))),
+    ));
+    game.names
+        .push((format!("computer ({difficulty})"), Color::Blue));
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    if let Some(name) = session_name(&session) {
+        forget_user(&mut universe_handle, &name);
+        game.names.push((name, Color::Red));
+    }
+    universe_handle.insert(uidx, game);
+    drop(universe_handle);
+    println!("universe {uidx}: playing against {difficulty} bot");
+
+    let _ = session.insert(USER_COLOR, "red");
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
+    HttpResponse::Ok().content_type("application/json").body(
+        serde_json::to_string(&ColorSender {
+            value: "red".into(),
+        })
+        .unwrap(),
+    )
+}
+
+#[post("/bot/join")]
+async fn bot_join(app_state: Data<AppState>, request: Json<BotJoin>) -> HttpResponse {
+    let join = request.into_inner();
+    if app_state.bot_key.is_some() && join.key != app_state.bot_key {
+        return HttpResponse::Unauthorized().body("wrong bot key");
+    }
+    let (uidx, color) = {
+        let mut universe_handle = app_state.universe.lock().unwrap();
+        let (uidx, color) = app_state.seat_player(&mut universe_handle);
+        let game = universe_handle.get_mut(&uidx).unwrap();
+        game.names.push((join.name.clone(), color.clone()));
+        (uidx, color)
+    };
+    let token = format!("{:032x}", rand::random::<u128>());
+    app_state
+        .bot_seats
+        .lock()
+        .unwrap()
+        .insert(token.clone(), (uidx, color.clone()));
+    println!("bot {} joined universe {uidx} as {:?}", join.name, color);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&BotJoined { token, color }).unwrap())
+}
+
+#[get("/bot/state")]
+async fn bot_state(app_state: Data<AppState>, request: HttpRequest) -> HttpResponse {
+    let (uidx, color) = match app_state.bot_seat(&request) {
+        Some(v) => v,
+        None => return HttpResponse::Unauthorized().body("unknown bot token"),
+    };
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match universe_handle.get_mut(&uidx) {
+        Some(v) => v,
+        None => return HttpResponse::NotFound().body("universe not found"),
+    };
+    game.heartbeat(&color);
+    game.check_heartbeats(app_state.grace_period);
+    game.run_clock();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&game.universe.bot_state(&color)).unwrap())
+}
+
+#[post("/bot/pick")]
+async fn bot_pick(
+    app_state: Data<AppState>,
+    request: HttpRequest,
+    action: Json<Action>,
+) -> HttpResponse {
+    let (uidx, color) = match app_state.bot_seat(&request) {
+        Some(v) => v,
+        None => return HttpResponse::Unauthorized().body("unknown bot token"),
+    };
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match universe_handle.get_mut(&uidx) {
+        Some(v) => v,
+        None => return HttpResponse::NotFound().body("universe not found"),
+    };
+    game.heartbeat(&color);
+    game.run_clock();
+    let accepted = game
+        .universe
+        .act(&color, &action.into_inner())
+        .unwrap_or(false);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&PickResult { accepted }).unwrap())
+}
+
+#[get("/user/pregame")]
+async fn pregame(app_state: Data<AppState>, session: Session) -> HttpResponse {
+    app_state.touch_user(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let ready = match session_universe(&session).and_then(|idx| universe_handle.get_mut(&idx)) {
+        Some(game) => {
+            // waiting counts as being present, so the first player does not forfeit
+            game.heartbeat(&session_color(&session));
+            game.universe.red_player_connected && game.universe.blue_player_connected
+        }
+        None => false,
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&PreGameData { ready }).unwrap())
+}
+
 #[get("/usernames/total/")]
-async fn active_users(app_state: Data<AppState>) -> HttpResponse {
+async fn active_users(app_state: Data<AppState>, session: Session) -> HttpResponse {
+    app_state.touch_user(&session);
     let users = match app_state.users.lock() {
         Ok(v) => v,
         Err(_) => return HttpResponse::Ok().body("Error. Could not get users"),
@@ -154,6 +699,7 @@ async fn active_users(app_state: Data<AppState>) -> HttpResponse {
     let user_list = UserList {
         users: users.clone(),
         n_users,
+        chat: app_state.lobby_chat.lock().unwrap().messages(),
     };
     HttpResponse::Ok()
         .content_type("application/json")
@@ -169,28 +715,401 @@ async fn get_deleted_input(request: Json<User>) -> Result<HttpResponse, JsonPayl
 
 #[get("/universe/universe")]
 async fn serve_universe(app_state: Data<AppState>, session: Session) -> HttpResponse {
-    let uidx = session.get::<usize>(USER_UNIVERSE_IDX).unwrap().unwrap() as u32;
-    let mut universe_handle = app_state.universe.lock().unwrap();
-    let uni = universe_handle.get_mut(&uidx).unwrap();
+    let uidx = match session_universe(&session) {
+        Some(uidx) => uidx,
+        None => return HttpResponse::NotFound().body("universe not found"),
+    };
+    app_state.touch_user(&session);
+    let bot_turn = {
+        let mut universe_handle = app_state.universe.lock().unwrap();
+        let game = match universe_handle.get_mut(&uidx) {
+            Some(v) => v,
+            None => return HttpResponse::NotFound().body("universe not found"),
+        };
+        game.heartbeat(&session_color(&session));
+        game.check_heartbeats(app_state.grace_period);
+        game.run_clock();
+        game.bot_turn()
+    };
+
+    if let Some((color, bot, snapshot)) = bot_turn {
+        // the search takes its whole time budget, keep it off the worker
+        let searched = web::block(move || {
+            let mut bot = match bot.try_lock() {
+                Ok(bot) => bot,
+                // a search that panicked left the bot usable
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                // another poll is already searching
+                Err(TryLockError::WouldBlock) => return None,
+            };
+            let pick = bot.pick(&snapshot, &color);
+            Some((color, pick, bot.stats().map(|s| s.win_rate)))
+        })
+        .await;
+        match searched {
+            Ok(Some((color, pick, estimate))) => {
+                match app_state.universe.lock().unwrap().get_mut(&uidx) {
+                    Some(game) => game.bot_played(&color, pick, estimate),
+                    None => return HttpResponse::NotFound().body("universe not found"),
+                }
+            }
+            Ok(None) => {}
+            Err(_) => {
+                return HttpResponse::InternalServerError().body("the computer player failed")
+            }
+        }
+    }
+    let universe_handle = app_state.universe.lock().unwrap();
+    let game = match universe_handle.get(&uidx) {
+        Some(v) => v,
+        None => return HttpResponse::NotFound().body("universe not found"),
+    };
+    let update = GameUpdate {
+        universe: game.universe.clone(),
+        chat: game.chat.messages(),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&update).unwrap())
+}
 
-    let mut au = app_state.active_user.lock().unwrap();
+/// Name messages of the session's user are posted under, their color if
+/// they never gave a name.
+fn chat_author(session: &Session) -> String {
+    session_name(session).unwrap_or_else(|| format!("{:?}", session_color(session)))
+}
 
-    if au.time.elapsed().as_secs_f64() >= 2.0 {
-        println!("evolve");
-        uni.evolve();
-        au.time = time::Instant::now();
-        au.cell_picked = false;
-        if au.status == Color::Red {
-            au.status = Color::Blue
-        } else if au.status == Color::Blue {
-            au.status = Color::Red
+/// Event stream of the lobby, starting with its current state.
+#[get("/lobby/feed")]
+async fn lobby_feed(app_state: Data<AppState>, session: Session) -> sse::Sse<sse::ChannelStream> {
+    app_state.touch_user(&session);
+    let name = session_name(&session);
+    if let Some(name) = &name {
+        // back in the lobby, so no longer watching a game
+        for game in app_state.universe.lock().unwrap().values_mut() {
+            game.names.retain(|n| !(n.0 == *name && n.1 == Color::None));
         }
     }
+    let (tx, rx) = sse::channel(LOBBY_FEED_BUFFER);
+    let lobby = serde_json::to_string(&app_state.lobby()).unwrap();
+    let _ = tx.try_send(sse::Data::new(lobby));
+    app_state.lobby_feed.lock().unwrap().push((name, tx));
+    rx
+}
+
+#[post("/lobby/challenge")]
+async fn challenge_user(
+    session: Session,
+    app_state: Data<AppState>,
+    request: Json<ChallengeTarget>,
+) -> HttpResponse {
+    let from = match session_name(&session) {
+        Some(name) => name,
+        None => return HttpResponse::Unauthorized().body("enter a name first"),
+    };
+    let ChallengeTarget { name: to, hints } = request.into_inner();
+    let known = app_state.users.lock().unwrap().iter().any(|u| u.name == to);
+    if to == from || !known {
+        return HttpResponse::BadRequest().body("unknown user");
+    }
+    let mut challenges = app_state.challenges.lock().unwrap();
+    if !challenges.iter().any(|c| c.from == from && c.to == to) {
+        println!("{from} challenges {to}");
+        challenges.push(Challenge {
+            from,
+            to,
+            hints,
+            universe: None,
+        });
+    }
+    HttpResponse::Ok().body("challenged")
+}
+
+/// Accepts a challenge: the user answering is seated as blue in a new
+/// universe, whose red seat is kept for the challenger.
+#[post("/lobby/accept")]
+async fn accept_challenge(
+    session: Session,
+    app_state: Data<AppState>,
+    request: Json<ChallengeTarget>,
+) -> HttpResponse {
+    let to = match session_name(&session) {
+        Some(name) => name,
+        None => return HttpResponse::Unauthorized().body("enter a name first"),
+    };
+    let from = request.into_inner().name;
+    let (uidx, hints) = {
+        let mut challenges = app_state.challenges.lock().unwrap();
+        let challenge = match challenges
+            .iter_mut()
+            .find(|c| c.from == from && c.to == to && c.universe.is_none())
+        {
+            Some(challenge) => challenge,
+            None => return HttpResponse::NotFound().body("no such challenge"),
+        };
+        let uidx = app_state.next_uni_id.fetch_add(1, Ordering::SeqCst);
+        challenge.universe = Some(uidx);
+        (uidx, challenge.hints)
+    };
+    let mut game = app_state.new_game(hints);
+    game.seat(&Color::Blue);
+    game.reserved_for = Some(from.clone());
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    forget_user(&mut universe_handle, &to);
+    game.names.push((to.clone(), Color::Blue));
+    universe_handle.insert(uidx, game);
+    println!("universe {uidx}: {to} accepted the challenge of {from}");
+
+    let _ = session.insert(USER_COLOR, "blue");
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
+    HttpResponse::Ok().content_type("application/json").body(
+        serde_json::to_string(&ColorSender {
+            value: "blue".into(),
+        })
+        .unwrap(),
+    )
+}
+
+/// Declines a challenge, or withdraws one's own.
+#[post("/lobby/decline")]
+async fn decline_challenge(
+    session: Session,
+    app_state: Data<AppState>,
+    request: Json<ChallengeTarget>,
+) -> HttpResponse {
+    let me = session_name(&session).unwrap_or_default();
+    let other = request.into_inner().name;
+    app_state.challenges.lock().unwrap().retain(|c| {
+        c.universe.is_some()
+            || !((c.from == other && c.to == me) || (c.from == me && c.to == other))
+    });
+    HttpResponse::Ok().body("declined")
+}
+
+/// Takes the free seat of an open room, or the one kept for the user after
+/// their challenge was accepted.
+#[post("/user/join/{uidx}")]
+async fn join_universe(
+    app_state: Data<AppState>,
+    session: Session,
+    uidx: web::Path<u32>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let uidx = uidx.into_inner();
+    let name = session_name(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match universe_handle.get_mut(&uidx) {
+        Some(game) if !game.universe.is_finished() => game,
+        _ => return HttpResponse::NotFound().body("universe not found"),
+    };
+    if game.invite.is_some() {
+        return HttpResponse::Forbidden().body("private games are joined through their invite");
+    }
+    if game.reserved_for.is_some() && game.reserved_for != name {
+        return HttpResponse::Forbidden().body("the seat is kept for someone else");
+    }
+    let color = if !game.universe.red_player_connected {
+        Color::Red
+    } else if !game.universe.blue_player_connected {
+        Color::Blue
+    } else {
+        return HttpResponse::Conflict().body("the room is full");
+    };
+    if let Some(name) = &name {
+        forget_user(&mut universe_handle, name);
+    }
+    let game = universe_handle.get_mut(&uidx).unwrap();
+    game.seat(&color);
+    game.reserved_for = None;
+    if let Some(name) = name {
+        game.names.push((name, color.clone()));
+    }
+    drop(universe_handle);
+    app_state
+        .challenges
+        .lock()
+        .unwrap()
+        .retain(|c| c.universe != Some(uidx));
+    println!("universe {uidx}: joined as {:?}", color);
+
+    let color = match color {
+        Color::Blue => "blue",
+        _ => "red",
+    };
+    let _ = session.insert(USER_COLOR, color);
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
+    HttpResponse::Ok().content_type("application/json").body(
+        serde_json::to_string(&ColorSender {
+            value: color.into(),
+        })
+        .unwrap(),
+    )
+}
 
-    uni.set_timer(au.time.elapsed().as_secs_f64());
+/// Creates a private game with the board and rules the user chose and seats
+/// them as red. Blue is taken through the invite link.
+#[post("/lobby/private")]
+async fn create_private_game(
+    session: Session,
+    app_state: Data<AppState>,
+    request: Json<PrivateGame>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let mut private = request.into_inner();
+    if let Err(e) = private.check() {
+        return HttpResponse::BadRequest().body(e);
+    }
+    // the creator can turn hints off, but not on against the server
+    private.settings.hints &= app_state.settings.hints;
+    // as many walls for the area as on the default board
+    let n_neutral = (N_NEUTRAL_BLOCKS as u64 * (private.width * private.height) as u64
+        / (WIDTH_UNIVERSE * HEIGHT_UNIVERSE) as u64) as u32;
+    let board = Universe::generate(
+        private.width as usize,
+        private.height as usize,
+        n_neutral,
+        rand::random(),
+    );
+    let mut game = Game::new(board.with_settings(private.settings));
+    game.seat(&Color::Red);
+    let token = format!("{:032x}", rand::random::<u128>());
+    game.invite = Some(token.clone());
+    let uidx = app_state.next_uni_id.fetch_add(1, Ordering::SeqCst);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    if let Some(name) = session_name(&session) {
+        forget_user(&mut universe_handle, &name);
+        game.names.push((name, Color::Red));
+    }
+    universe_handle.insert(uidx, game);
+    println!(
+        "universe {uidx}: private {}x{} game",
+        private.width, private.height
+    );
+
+    let _ = session.insert(USER_COLOR, "red");
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
     HttpResponse::Ok()
         .content_type("application/json")
-        .body(serde_json::to_string(&uni.clone()).unwrap())
+        .body(serde_json::to_string(&PrivateGameCreated { token }).unwrap())
+}
+
+/// Takes the second seat of the private game the invite token belongs to.
+#[post("/user/invite/{token}")]
+async fn accept_invite(
+    app_state: Data<AppState>,
+    session: Session,
+    token: web::Path<String>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let token = token.into_inner();
+    let name = session_name(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let found = universe_handle
+        .iter()
+        .find(|(_, game)| game.invite.as_ref() == Some(&token));
+    let uidx = match found {
+        Some((&uidx, game)) if !game.universe.is_finished() => {
+            if game.universe.blue_player_connected {
+                return HttpResponse::Conflict().body("someone already took the seat");
+            }
+            uidx
+        }
+        _ => return HttpResponse::NotFound().body("this invite is no longer valid"),
+    };
+    // the creator opening their own link
+    if session_universe(&session) == Some(uidx) && session_color(&session) != Color::None {
+        return HttpResponse::Conflict().body("you are already playing in this game");
+    }
+    if let Some(name) = &name {
+        forget_user(&mut universe_handle, name);
+    }
+    let game = universe_handle.get_mut(&uidx).unwrap();
+    game.seat(&Color::Blue);
+    if let Some(name) = name {
+        game.names.push((name, Color::Blue));
+    }
+    println!("universe {uidx}: invite accepted");
+
+    let _ = session.insert(USER_COLOR, "blue");
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
+    HttpResponse::Ok().content_type("application/json").body(
+        serde_json::to_string(&ColorSender {
+            value: "blue".into(),
+        })
+        .unwrap(),
+    )
+}
+
+/// Seats the user as a spectator: they get the universe like the players,
+/// but have no color to act with.
+#[post("/universe/spectate/{uidx}")]
+async fn spectate(
+    session: Session,
+    app_state: Data<AppState>,
+    uidx: web::Path<u32>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let uidx = uidx.into_inner();
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    match universe_handle.get(&uidx) {
+        None => return HttpResponse::NotFound().body("universe not found"),
+        Some(game) if game.invite.is_some() => {
+            return HttpResponse::Forbidden().body("private games cannot be watched")
+        }
+        Some(_) => {}
+    }
+    if let Some(name) = session_name(&session) {
+        forget_user(&mut universe_handle, &name);
+        let game = universe_handle.get_mut(&uidx).unwrap();
+        game.names.push((name, Color::None));
+    }
+    session.remove(USER_COLOR);
+    let _ = session.insert(USER_UNIVERSE_IDX, uidx);
+    HttpResponse::Ok().body("spectating")
+}
+
+#[post("/usernames/chat")]
+async fn lobby_chat(
+    session: Session,
+    app_state: Data<AppState>,
+    http_request: HttpRequest,
+    request: Json<ChatPost>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let mut chat = app_state.lobby_chat.lock().unwrap();
+    match chat.post(
+        &chat_sender(&session, &http_request),
+        &chat_author(&session),
+        &request,
+        now_ms(),
+    ) {
+        Ok(()) => HttpResponse::Ok().body("posted"),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[post("/universe/chat")]
+async fn universe_chat(
+    session: Session,
+    app_state: Data<AppState>,
+    http_request: HttpRequest,
+    request: Json<ChatPost>,
+) -> HttpResponse {
+    app_state.touch_user(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match session_universe(&session).and_then(|idx| universe_handle.get_mut(&idx)) {
+        Some(game) => game,
+        None => return HttpResponse::NotFound().body("universe not found"),
+    };
+    match game.chat.post(
+        &chat_sender(&session, &http_request),
+        &chat_author(&session),
+        &request,
+        now_ms(),
+    ) {
+        Ok(()) => HttpResponse::Ok().body("posted"),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
 }
 
 #[post("/universe/kill")]
@@ -198,64 +1117,178 @@ async fn kill_universe(
     session: Session,
     app_state: Data<AppState>,
 ) -> Result<HttpResponse, JsonPayloadError> {
-    let name = session.remove(USER_NAME).unwrap();
+    let name = session.get::<String>(USER_NAME).unwrap_or(None);
+    let color = session_color(&session);
+    if let Some(uidx) = session_universe(&session) {
+        // leaving a running game gives it up, the universe itself is reaped
+        // once the opponent had time to see the result
+        let mut universes = app_state.universe.lock().unwrap();
+        if let Some(game) = universes.get_mut(&uidx) {
+            game.leave(&color);
+        }
+        if let Some(name) = &name {
+            forget_user(&mut universes, name);
+        }
+    }
+    session.remove(USER_NAME);
     session.remove(USER_COLOR);
     session.remove(USER_UNIVERSE_IDX);
-    let mut users = app_state.users.lock().unwrap();
-    let uidx = &app_state.uni_id;
-    let mut universes = app_state.universe.lock().unwrap();
-    universes.remove(&uidx.load(Ordering::SeqCst));
-    for i in 0..users.len() {
-        if users[i].name == name {
-            users.remove(i);
-            break;
-        }
+    if let Some(name) = name {
+        // one lock at a time, `reap` takes them in the other order
+        app_state.user_seen.lock().unwrap().remove(&name);
+        app_state.users.lock().unwrap().retain(|u| u.name != name);
+        app_state
+            .challenges
+            .lock()
+            .unwrap()
+            .retain(|c| c.from != name && c.to != name);
+        app_state.release_seats();
     }
     Ok(HttpResponse::Ok().body("Universe deleted"))
 }
 
-#[post("/universe/cellpick")]
-async fn cell_picked(
+#[get("/metrics")]
+async fn metrics(app_state: Data<AppState>) -> HttpResponse {
+    let metrics = Metrics {
+        universes: app_state.universe.lock().unwrap().len(),
+        users: app_state.users.lock().unwrap().len(),
+        reaped_universes: app_state.reaped_universes.load(Ordering::SeqCst),
+        reaped_users: app_state.reaped_users.load(Ordering::SeqCst),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&metrics).unwrap())
+}
+
+#[post("/universe/resign")]
+async fn resign(session: Session, app_state: Data<AppState>) -> HttpResponse {
+    let color = session_color(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match session_universe(&session).and_then(|idx| universe_handle.get_mut(&idx)) {
+        Some(game) => game,
+        None => return HttpResponse::NotFound().body("no game to resign"),
+    };
+    if color == Color::None || game.universe.is_finished() {
+        return HttpResponse::Conflict().body("nothing to resign");
+    }
+    game.leave(&color);
+    HttpResponse::Ok().body("resigned")
+}
+
+/// Offers a draw, or accepts the opponent's standing offer.
+#[post("/universe/draw")]
+async fn offer_draw(session: Session, app_state: Data<AppState>) -> HttpResponse {
+    let color = session_color(&session);
+    let mut universe_handle = app_state.universe.lock().unwrap();
+    let game = match session_universe(&session).and_then(|idx| universe_handle.get_mut(&idx)) {
+        Some(game) => game,
+        None => return HttpResponse::NotFound().body("no game to offer a draw in"),
+    };
+    if !game.universe.offer_draw(&color) {
+        return HttpResponse::Conflict().body("no draw to offer");
+    }
+    HttpResponse::Ok().body("draw offered")
+}
+
+#[post("/universe/action")]
+async fn universe_action(
     session: Session,
     app_state: Data<AppState>,
-    request: Json<(f64, f64)>,
+    request: Json<Action>,
 ) -> Result<HttpResponse, JsonPayloadError> {
-    let c = session.get::<String>(USER_COLOR).unwrap().unwrap();
-    let uidx = session.get::<usize>(USER_UNIVERSE_IDX).unwrap().unwrap() as u32;
-    let cell = if c == "red" {
-        Cell::Red
-    } else if c == "blue" {
-        Cell::Blue
-    } else {
-        unreachable!()
+    let color = session_color(&session);
+    let mut universe = app_state.universe.lock().unwrap();
+    let game = match session_universe(&session).and_then(|idx| universe.get_mut(&idx)) {
+        Some(game) => game,
+        None => return Ok(HttpResponse::NotFound().body("universe not found")),
     };
-    let mut au = app_state.active_user.lock().unwrap();
-    if au.status == cell.to_color() && au.cell_picked == false {
-        au.cell_picked = true;
-        drop(au);
-
-        let coords = request.into_inner();
-        let coords = (coords.1 as usize, coords.0 as usize);
-        let mut universe = app_state.universe.lock().unwrap();
-        let _ = universe
-            .get_mut(&uidx)
-            .unwrap()
-            .set_cell(&cell, coords)
-            .unwrap();
-    }
-    Ok(HttpResponse::Ok().body("angekommen"))
+    game.run_clock();
+
+    let used = game
+        .universe
+        .act(&color, &request.into_inner())
+        .unwrap_or(false);
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&used).unwrap()))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "debug");
     std::env::set_var("RUST_TRACE", "1");
+    let grace_period = std::env::var("CHERRY_GRACE_PERIOD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_GRACE_PERIOD);
+    let mut settings = GameSettings::default();
+    if let Ok(v) = std::env::var("CHERRY_VICTORY_RULE") {
+        settings.victory_rule = v.parse().expect("invalid CHERRY_VICTORY_RULE");
+    }
+    if let Ok(v) = std::env::var("CHERRY_CLOCK") {
+        settings.clock.mode = v.parse().expect("invalid CHERRY_CLOCK");
+    }
+    if let Ok(v) = std::env::var("CHERRY_TURN_LENGTH") {
+        settings.clock.turn_length = v.parse().expect("invalid CHERRY_TURN_LENGTH");
+    }
+    settings.clock.pick_ends_turn = std::env::var("CHERRY_PICK_ENDS_TURN").is_ok();
+    settings.capture = std::env::var("CHERRY_CAPTURE").is_ok();
+    if let Ok(v) = std::env::var("CHERRY_DECAY") {
+        settings.decay = Some(v.parse().expect("invalid CHERRY_DECAY"));
+    }
+    settings.hints = std::env::var("CHERRY_NO_HINTS").is_err();
+    let map = std::env::var("CHERRY_MAP").ok().map(|path| {
+        let text = std::fs::read_to_string(&path).expect("could not read CHERRY_MAP");
+        Universe::from_map(&text).unwrap_or_else(|e| panic!("invalid map {}: {}", path, e))
+    });
+    let (width, height) = match &map {
+        Some(map) => (map.width(), map.height()),
+        None => (WIDTH_UNIVERSE as usize, HEIGHT_UNIVERSE as usize),
+    };
+    if let Err(e) = settings.check(width, height) {
+        panic!("invalid game settings: {}", e);
+    }
     let app_state = web::Data::new(AppState {
         app_name: String::from("Actix Web"),
         users: Mutex::new(vec![]),
-        universe: Mutex::new(HashMap::from([(0, Universe::new_rand())])),
-        active_user: Mutex::new(ActiveUser::new()),
+        user_seen: Mutex::new(HashMap::new()),
+        universe: Mutex::new(HashMap::new()),
         uni_id: AtomicU32::new(0),
+        next_uni_id: AtomicU32::new(1),
+        grace_period: time::Duration::from_secs(grace_period),
+        settings,
+        map,
+        reaped_universes: AtomicU32::new(0),
+        reaped_users: AtomicU32::new(0),
+        bot_key: std::env::var("CHERRY_BOT_KEY").ok(),
+        bot_seats: Mutex::new(HashMap::new()),
+        lobby_chat: Mutex::new(Chat::new()),
+        lobby_feed: Mutex::new(vec![]),
+        last_lobby: Mutex::new(String::new()),
+        challenges: Mutex::new(vec![]),
+    });
+
+    let reaper_state = app_state.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(REAP_INTERVAL);
+        loop {
+            interval.tick().await;
+            reaper_state.reap();
+        }
+    });
+
+    let lobby_state = app_state.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(LOBBY_PUSH_INTERVAL);
+        let mut last_keep_alive = time::Instant::now();
+        loop {
+            interval.tick().await;
+            let keep_alive = last_keep_alive.elapsed() >= LOBBY_KEEP_ALIVE;
+            if keep_alive {
+                last_keep_alive = time::Instant::now();
+            }
+            lobby_state.push_lobby(keep_alive);
+        }
     });
 
     let secret_key = Key::generate();
@@ -271,8 +1304,27 @@ async fn main() -> std::io::Result<()> {
                 web::scope("/api")
                     .service(active_users)
                     .service(register_user)
-                    .service(cell_picked)
+                    .service(universe_action)
                     .service(serve_universe)
+                    .service(resign)
+                    .service(offer_draw)
+                    .service(kill_universe)
+                    .service(metrics)
+                    .service(pregame)
+                    .service(seat_bot)
+                    .service(bot_join)
+                    .service(bot_state)
+                    .service(bot_pick)
+                    .service(lobby_chat)
+                    .service(universe_chat)
+                    .service(lobby_feed)
+                    .service(challenge_user)
+                    .service(accept_challenge)
+                    .service(decline_challenge)
+                    .service(join_universe)
+                    .service(spectate)
+                    .service(create_private_game)
+                    .service(accept_invite)
                     .service(give_user_color),
             )
             .service(
@@ -287,3 +1339,250 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{
+        call_and_read_body_json, call_service, init_service, read_body_json, TestRequest,
+    };
+    use common::api::BotState;
+
+    fn app_state() -> Data<AppState> {
+        Data::new(AppState {
+            app_name: String::from("test"),
+            users: Mutex::new(vec![]),
+            user_seen: Mutex::new(HashMap::new()),
+            universe: Mutex::new(HashMap::new()),
+            uni_id: AtomicU32::new(0),
+            next_uni_id: AtomicU32::new(1),
+            grace_period: time::Duration::from_secs(DEFAULT_GRACE_PERIOD),
+            settings: GameSettings::default(),
+            map: Some(Universe::from_map(".....").unwrap()),
+            reaped_universes: AtomicU32::new(0),
+            reaped_users: AtomicU32::new(0),
+            bot_key: None,
+            bot_seats: Mutex::new(HashMap::new()),
+            lobby_chat: Mutex::new(Chat::new()),
+            lobby_feed: Mutex::new(vec![]),
+            last_lobby: Mutex::new(String::new()),
+            challenges: Mutex::new(vec![]),
+        })
+    }
+
+    fn seated_game() -> Game {
+        let mut universe = Universe::from_map(".....").unwrap();
+        universe.red_player_connected = true;
+        universe.blue_player_connected = true;
+        Game::new(universe)
+    }
+
+    fn ago(secs: u64) -> time::Instant {
+        time::Instant::now()
+            .checked_sub(time::Duration::from_secs(secs))
+            .unwrap()
+    }
+
+    #[test]
+    fn bot_stays_seated_while_it_searches() {
+        let mut game = seated_game();
+        game.bot = Some((Color::Blue, Arc::new(Mutex::new(Difficulty::Easy.bot(1)))));
+        assert!(game.bot_turn().is_none());
+        game.universe.advance_clock(2.);
+        let (color, bot, snapshot) = game.bot_turn().unwrap();
+        // a search that never reports back leaves the bot in the game
+        assert!(game.bot.is_some());
+        let pick = bot.lock().unwrap().pick(&snapshot, &color);
+        game.bot_played(&color, pick, None);
+        assert!(game.universe.get_clock().picked);
+    }
+
+    #[test]
+    fn leaving_an_unstarted_game_abandons_it() {
+        let mut game = Game::new(Universe::from_map(".....").unwrap());
+        game.seat(&Color::Red);
+        game.leave(&Color::Red);
+        assert_eq!(game.universe.get_outcome(), Some(GameOutcome::Abandoned));
+
+        let mut game = seated_game();
+        game.leave(&Color::Red);
+        assert_eq!(
+            game.universe.get_outcome(),
+            Some(GameOutcome::Forfeit(Color::Blue))
+        );
+    }
+
+    #[test]
+    fn heartbeats_forfeit_the_missing_player() {
+        let grace = time::Duration::from_secs(10);
+        let mut game = seated_game();
+        game.check_heartbeats(grace);
+        assert!(!game.universe.is_finished());
+        game.blue_seen = ago(20);
+        game.check_heartbeats(grace);
+        assert_eq!(
+            game.universe.get_outcome(),
+            Some(GameOutcome::Forfeit(Color::Red))
+        );
+
+        let mut game = seated_game();
+        game.red_seen = ago(20);
+        game.blue_seen = ago(20);
+        game.check_heartbeats(grace);
+        assert_eq!(game.universe.get_outcome(), Some(GameOutcome::Abandoned));
+    }
+
+    #[test]
+    fn clock_waits_for_the_second_player() {
+        let settings = GameSettings {
+            clock: ClockSettings {
+                mode: ClockMode::Bank {
+                    initial: 60.,
+                    increment: 0.,
+                },
+                ..ClockSettings::default()
+            },
+            ..GameSettings::default()
+        };
+        let mut game = Game::new(Universe::from_map(".....").unwrap().with_settings(settings));
+        game.seat(&Color::Red);
+        game.last_tick = ago(70);
+        game.run_clock();
+        assert!(!game.universe.is_finished());
+        game.last_tick = ago(70);
+        game.seat(&Color::Blue);
+        game.run_clock();
+        assert!(!game.universe.is_finished());
+        assert!(game.universe.get_clock().bank(&Color::Red) > 59.);
+    }
+
+    #[actix_web::test]
+    async fn bots_join_read_the_state_and_pick() {
+        let app = init_service(
+            App::new()
+                .app_data(app_state())
+                .service(bot_join)
+                .service(bot_state)
+                .service(bot_pick),
+        )
+        .await;
+        let mut seats = vec![];
+        for name in ["red-bot", "blue-bot"] {
+            let join = TestRequest::post()
+                .uri("/bot/join")
+                .set_json(BotJoin {
+                    name: name.into(),
+                    key: None,
+                })
+                .to_request();
+            let joined: BotJoined = call_and_read_body_json(&app, join).await;
+            seats.push(joined);
+        }
+        assert_eq!(seats[0].color, Color::Red);
+        assert_eq!(seats[1].color, Color::Blue);
+        let bearer = |joined: &BotJoined| ("Authorization", format!("Bearer {}", joined.token));
+
+        let state = TestRequest::get()
+            .uri("/bot/state")
+            .insert_header(bearer(&seats[0]))
+            .to_request();
+        let state: BotState = call_and_read_body_json(&app, state).await;
+        assert!(state.started && state.your_turn);
+        assert_eq!(state.cells[2], Cell::Empty);
+
+        // only the bot whose turn it is gets its pick in
+        for (joined, accepted) in [(&seats[1], false), (&seats[0], true)] {
+            let pick = TestRequest::post()
+                .uri("/bot/pick")
+                .insert_header(bearer(joined))
+                .set_json(Action::Pick((2, 0)))
+                .to_request();
+            let result: PickResult = call_and_read_body_json(&app, pick).await;
+            assert_eq!(result.accepted, accepted);
+        }
+        let state = TestRequest::get()
+            .uri("/bot/state")
+            .insert_header(bearer(&seats[0]))
+            .to_request();
+        let state: BotState = call_and_read_body_json(&app, state).await;
+        assert!(!state.your_turn);
+        assert_eq!(state.cells[2], Cell::Red);
+
+        let unknown = TestRequest::get()
+            .uri("/bot/state")
+            .insert_header(("Authorization", "Bearer nope"))
+            .to_request();
+        let response = call_service(&app, unknown).await;
+        assert_eq!(response.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn chat_limit_survives_cleared_cookies() {
+        let app = init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                ))
+                .app_data(app_state())
+                .service(lobby_chat),
+        )
+        .await;
+        // no cookie is ever sent back, as if they were cleared every time
+        let post = |addr: &str| {
+            TestRequest::post()
+                .uri("/usernames/chat")
+                .peer_addr(addr.parse().unwrap())
+                .set_json(ChatPost {
+                    text: "hi".into(),
+                    emote: false,
+                })
+                .to_request()
+        };
+        for _ in 0..CHAT_BURST {
+            let response = call_service(&app, post("10.0.0.1:4000")).await;
+            assert!(response.status().is_success());
+        }
+        let response = call_service(&app, post("10.0.0.1:4001")).await;
+        assert_eq!(response.status(), 400);
+        let response = call_service(&app, post("10.0.0.2:4000")).await;
+        assert!(response.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn invites_are_checked_before_seating() {
+        let app = init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                ))
+                .app_data(app_state())
+                .service(create_private_game)
+                .service(accept_invite),
+        )
+        .await;
+        let create = TestRequest::post()
+            .uri("/lobby/private")
+            .set_json(PrivateGame {
+                width: 10,
+                height: 10,
+                settings: GameSettings::default(),
+            })
+            .to_request();
+        let response = call_service(&app, create).await;
+        assert!(response.status().is_success());
+        let cookie = response.response().cookies().next().unwrap().into_owned();
+        let created: PrivateGameCreated = read_body_json(response).await;
+        let accept = |token: &str| TestRequest::post().uri(&format!("/user/invite/{}", token));
+
+        let response = call_service(&app, accept("nope").to_request()).await;
+        assert_eq!(response.status(), 404);
+        let own_link = accept(&created.token).cookie(cookie).to_request();
+        let response = call_service(&app, own_link).await;
+        assert_eq!(response.status(), 409);
+        // the seat is still free for the invited player
+        let response = call_service(&app, accept(&created.token).to_request()).await;
+        assert!(response.status().is_success());
+    }
+}
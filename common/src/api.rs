@@ -0,0 +1,123 @@
+//! JSON exchanged with external bots. A bot joins with `POST /api/bot/join`
+//! and gets a token for its seat, which it sends as `Authorization: Bearer
+//! <token>` on every other request. It then polls `GET /api/bot/state` and,
+//! when `your_turn` is set, answers with `POST /api/bot/pick` before
+//! `time_left` runs out:
+//!
+//! ```text
+//! POST /api/bot/join   {"name": "my-bot", "key": null}
+//!                   -> {"token": "3f0c…", "color": "Blue"}
+//! GET  /api/bot/state  -> BotState
+//! POST /api/bot/pick   {"Pick": [3, 7]}  or any other `Action`
+//!                   -> {"accepted": true}
+//! ```
+//!
+//! Cells are listed row by row, the cell at `(x, y)` has index
+//! `x + y * width`. A bot that stops polling for the grace period forfeits
+//! like any other player.
+
+use crate::{Abilities, Cell, ClockMode, Color, GameOutcome, GameSettings, Terrain, Universe};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BotJoin {
+    pub name: String,
+    /// needed when the server was started with CHERRY_BOT_KEY
+    pub key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BotJoined {
+    pub token: String,
+    pub color: Color,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BotState {
+    pub color: Color,
+    /// both seats are taken and the game is running
+    pub started: bool,
+    pub your_turn: bool,
+    /// seconds left to act this turn, cut short by the clock bank when that
+    /// runs out first
+    pub time_left: f64,
+    pub tick: u32,
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+    pub terrain: Vec<Terrain>,
+    pub abilities: Abilities,
+    pub settings: GameSettings,
+    pub outcome: Option<GameOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PickResult {
+    pub accepted: bool,
+}
+
+impl Universe {
+    /// The universe as `color`'s bot sees it.
+    pub fn bot_state(&self, color: &Color) -> BotState {
+        let clock = self.get_clock();
+        let settings = self.get_settings();
+        let mut time_left = (settings.clock.turn_length - clock.elapsed).max(0.);
+        if let ClockMode::Bank { .. } = settings.clock.mode {
+            time_left = time_left.min(clock.bank(color));
+        }
+        let started = self.red_player_connected && self.blue_player_connected;
+        BotState {
+            color: color.clone(),
+            started,
+            your_turn: started && !self.is_finished() && clock.turn == *color && !clock.picked,
+            time_left,
+            tick: self.get_tick(),
+            width: self.width(),
+            height: self.height(),
+            cells: self.get_cells(),
+            terrain: self.get_terrain(),
+            abilities: self.get_abilities(color).clone(),
+            settings: settings.clone(),
+            outcome: self.get_outcome(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClockSettings;
+
+    fn banked(initial: f64, turn_length: f64) -> Universe {
+        let mut uni = Universe::from_map(".....")
+            .unwrap()
+            .with_settings(GameSettings {
+                clock: ClockSettings {
+                    mode: ClockMode::Bank {
+                        initial,
+                        increment: 0.,
+                    },
+                    turn_length,
+                    pick_ends_turn: true,
+                },
+                ..GameSettings::default()
+            });
+        uni.red_player_connected = true;
+        uni.blue_player_connected = true;
+        uni
+    }
+
+    #[test]
+    fn time_left_is_cut_short_by_the_bank() {
+        let mut uni = banked(5., 30.);
+        uni.advance_clock(1.);
+        let red = uni.bot_state(&Color::Red);
+        assert!(red.your_turn);
+        assert_eq!(red.time_left, 4.);
+        assert!(!uni.bot_state(&Color::Blue).your_turn);
+        // with plenty in the bank the turn ends first
+        let mut uni = banked(60., 2.);
+        uni.advance_clock(0.5);
+        assert_eq!(uni.bot_state(&Color::Red).time_left, 1.5);
+    }
+}
@@ -0,0 +1,517 @@
+//! Computer opponents. A bot looks at the universe and picks a cell for its
+//! color, harder bots look further ahead.
+
+use crate::{cell_of, Cell, Color, Coords, GameOutcome, Universe, VictoryRule};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
+use std::str::FromStr;
+
+pub trait Bot {
+    /// The cell to claim this turn, `None` if there is nothing left to pick.
+    fn pick(&mut self, universe: &Universe, color: &Color) -> Option<Coords>;
+
+    /// What the last search found, for bots that search.
+    fn stats(&self) -> Option<SearchStats> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchStats {
+    pub playouts: u32,
+    /// share of the playouts through the chosen pick the bot won
+    pub win_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    pub fn bot(self, seed: u64) -> Box<dyn Bot + Send> {
+        match self {
+            Difficulty::Easy => Box::new(RandomBot::new(seed)),
+            Difficulty::Medium => Box::new(GreedyBot::new(seed)),
+            Difficulty::Hard => Box::new(LookaheadBot::new(seed, 3, 8)),
+            Difficulty::Expert => Box::new(MctsBot::new(seed, 250.)),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+            Difficulty::Expert => write!(f, "expert"),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            "expert" => Ok(Difficulty::Expert),
+            _ => Err(format!("unknown difficulty {}", s)),
+        }
+    }
+}
+
+/// Small xorshift generator, so bots can be seeded and common needs no
+/// extra dependency that would have to work in the browser too.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must never be zero
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform number in `0..n`, `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Indices of the cells a pick can claim.
+pub fn free_cells(universe: &Universe) -> Vec<usize> {
+    let objective = match universe.settings.victory_rule {
+        VictoryRule::Objective(coords) => universe.get_index(coords).ok(),
+        _ => None,
+    };
+    (0..universe.cells.len())
+        .filter(|&idx| universe.cells[idx] == Cell::Empty && Some(idx) != objective)
+        .collect()
+}
+
+/// Empty neighbours of `idx` that no cell of `color` borders yet, i.e. how
+/// much a pick there grows the color's frontier.
+pub fn frontier_gain(universe: &Universe, color: &Color, idx: usize) -> u32 {
+    let own = cell_of(color);
+    universe
+        .get_neighbours(universe.get_coords(idx))
+        .into_iter()
+        .filter(|&n| {
+            universe.cells[n] == Cell::Empty
+                && !universe
+                    .get_neighbours(universe.get_coords(n))
+                    .into_iter()
+                    .any(|m| universe.cells[m] == own)
+        })
+        .count() as u32
+}
+
+/// Free cells paired with their frontier gain, like `frontier_gain` but
+/// marking the cells `color` already borders once for the whole board.
+fn gains(universe: &Universe, color: &Color) -> Vec<(u32, usize)> {
+    let own = cell_of(color);
+    let mut bordered = vec![false; universe.cells.len()];
+    for idx in 0..universe.cells.len() {
+        if universe.cells[idx] == own {
+            for n in universe.get_neighbours(universe.get_coords(idx)) {
+                bordered[n] = true;
+            }
+        }
+    }
+    free_cells(universe)
+        .into_iter()
+        .map(|idx| {
+            let gain = universe
+                .get_neighbours(universe.get_coords(idx))
+                .filter(|&n| universe.cells[n] == Cell::Empty && !bordered[n])
+                .count() as u32;
+            (gain, idx)
+        })
+        .collect()
+}
+
+/// Free cells with the highest frontier gain first.
+fn ranked_cells(universe: &Universe, color: &Color) -> Vec<(u32, usize)> {
+    let mut ranked = gains(universe, color);
+    ranked.sort_by_key(|c| Reverse(c.0));
+    ranked
+}
+
+/// The `n` free cells with the highest frontier gain. Equally ranked cells
+/// are ordered at random, so the candidates are not always the top left ones.
+fn candidates(universe: &Universe, color: &Color, rng: &mut Rng, n: usize) -> Vec<usize> {
+    let mut ranked: Vec<(u32, u64, usize)> = gains(universe, color)
+        .into_iter()
+        .map(|(gain, idx)| (gain, rng.next_u64(), idx))
+        .collect();
+    let n = n.min(ranked.len());
+    if n == 0 {
+        return vec![];
+    }
+    ranked.select_nth_unstable_by_key(n - 1, |c| Reverse((c.0, c.1)));
+    ranked.truncate(n);
+    ranked.sort_unstable_by_key(|c| Reverse((c.0, c.1)));
+    ranked.into_iter().map(|(_, _, idx)| idx).collect()
+}
+
+/// Milliseconds since some fixed point in time. `Instant` is not available
+/// in the browser, where bots run in offline games.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64() * 1000.)
+        .unwrap_or(0.)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    pub fn now_ms() -> f64;
+}
+
+/// Picks any free cell.
+pub struct RandomBot {
+    rng: Rng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn pick(&mut self, universe: &Universe, _color: &Color) -> Option<Coords> {
+        let free = free_cells(universe);
+        if free.is_empty() {
+            return None;
+        }
+        Some(universe.get_coords(free[self.rng.below(free.len())]))
+    }
+}
+
+/// Picks the cell that grows its frontier the most, ties are broken at random.
+pub struct GreedyBot {
+    rng: Rng,
+}
+
+impl GreedyBot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Bot for GreedyBot {
+    fn pick(&mut self, universe: &Universe, color: &Color) -> Option<Coords> {
+        let ranked = ranked_cells(universe, color);
+        let best = ranked.first()?.0;
+        let n_best = ranked.iter().take_while(|c| c.0 == best).count();
+        Some(universe.get_coords(ranked[self.rng.below(n_best)].1))
+    }
+}
+
+/// Tries the `width` most promising cells and plays each of them out for
+/// `depth` ticks on a cloned universe, with both sides answering greedily.
+/// Keeps the pick that leaves it the most guaranteed territory.
+pub struct LookaheadBot {
+    depth: u32,
+    width: usize,
+    rng: Rng,
+}
+
+impl LookaheadBot {
+    pub fn new(seed: u64, depth: u32, width: usize) -> Self {
+        Self {
+            depth,
+            width,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn play_out(&self, universe: &Universe, color: &Color, idx: usize) -> i64 {
+        let mut sim = universe.clone();
+        let _ = sim.set_cell(&cell_of(color), sim.get_coords(idx));
+        let mut to_move = color.opponent();
+        for _ in 0..self.depth {
+            sim.evolve();
+            if sim.is_finished() {
+                break;
+            }
+            if let Some(&(_, reply)) = ranked_cells(&sim, &to_move).first() {
+                let _ = sim.set_cell(&cell_of(&to_move), sim.get_coords(reply));
+            }
+            to_move = to_move.opponent();
+        }
+        let (red, blue, _) = sim.territory();
+        match color {
+            Color::Blue => blue as i64 - red as i64,
+            _ => red as i64 - blue as i64,
+        }
+    }
+}
+
+impl Bot for LookaheadBot {
+    fn pick(&mut self, universe: &Universe, color: &Color) -> Option<Coords> {
+        candidates(universe, color, &mut self.rng, self.width)
+            .into_iter()
+            .map(|idx| (self.play_out(universe, color, idx), idx))
+            .max_by_key(|&(score, _)| score)
+            .map(|(_, idx)| universe.get_coords(idx))
+    }
+}
+
+struct Node {
+    /// cell claimed on the way into this node
+    pick: usize,
+    visits: u32,
+    /// playouts won by the color that made `pick`
+    wins: f64,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+}
+
+impl Node {
+    fn new(pick: usize, untried: Vec<usize>) -> Self {
+        Self {
+            pick,
+            visits: 0,
+            wins: 0.,
+            children: vec![],
+            untried,
+        }
+    }
+}
+
+/// Monte Carlo tree search. Each node only considers the `width` cells
+/// with the best frontier gain, playouts pick the best of a few random
+/// cells and are scored after `horizon` ticks. Searches until the time
+/// budget or the playout limit runs out, whichever comes first.
+pub struct MctsBot {
+    rng: Rng,
+    budget_ms: f64,
+    max_playouts: u32,
+    width: usize,
+    horizon: u32,
+    nodes: Vec<Node>,
+    /// universe the playouts run on, reset from the real one every time
+    scratch: Option<Universe>,
+    stats: Option<SearchStats>,
+}
+
+impl MctsBot {
+    pub fn new(seed: u64, budget_ms: f64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            budget_ms,
+            max_playouts: u32::MAX,
+            width: 12,
+            horizon: 8,
+            nodes: vec![],
+            scratch: None,
+            stats: None,
+        }
+    }
+
+    /// Caps the playouts per pick, which makes the search reproducible.
+    pub fn with_max_playouts(mut self, max_playouts: u32) -> Self {
+        self.max_playouts = max_playouts;
+        self
+    }
+
+    fn play(sim: &mut Universe, color: &Color, idx: usize) {
+        let _ = sim.set_cell(&cell_of(color), sim.get_coords(idx));
+        sim.evolve();
+    }
+
+    /// Best of a few random cells, cheap enough to call on every playout tick.
+    fn rollout_pick(&mut self, sim: &Universe, color: &Color) -> Option<usize> {
+        (0..8)
+            .map(|_| self.rng.below(sim.cells.len()))
+            .filter(|&idx| sim.cells[idx] == Cell::Empty)
+            .max_by_key(|&idx| frontier_gain(sim, color, idx))
+    }
+
+    /// Plays on from `sim` and scores the result for `color`: 1 for a win,
+    /// 0 for a loss and 0.5 for a draw.
+    fn rollout(&mut self, sim: &mut Universe, mut to_move: Color, color: &Color) -> f64 {
+        for _ in 0..self.horizon {
+            if sim.is_finished() {
+                break;
+            }
+            match self.rollout_pick(sim, &to_move) {
+                Some(idx) => Self::play(sim, &to_move, idx),
+                None => sim.evolve(),
+            }
+            to_move = to_move.opponent();
+        }
+        let winner = match sim.get_outcome() {
+            Some(GameOutcome::Territory(c))
+            | Some(GameOutcome::Connected(c))
+            | Some(GameOutcome::Objective(c))
+            | Some(GameOutcome::Forfeit(c)) => c,
+            Some(_) => Color::None,
+            None => {
+                let (red, blue, _) = sim.territory();
+                match red.cmp(&blue) {
+                    Ordering::Greater => Color::Red,
+                    Ordering::Less => Color::Blue,
+                    Ordering::Equal => Color::None,
+                }
+            }
+        };
+        if winner == *color {
+            1.
+        } else if winner == Color::None {
+            0.5
+        } else {
+            0.
+        }
+    }
+
+    fn select(&self, node: usize) -> usize {
+        let parent = &self.nodes[node];
+        let ln_visits = (parent.visits as f64).ln();
+        *parent
+            .children
+            .iter()
+            .max_by(|&&a, &&b| {
+                let uct = |n: &Node| {
+                    n.wins / n.visits as f64 + 1.4 * (ln_visits / n.visits as f64).sqrt()
+                };
+                uct(&self.nodes[a]).total_cmp(&uct(&self.nodes[b]))
+            })
+            .unwrap()
+    }
+}
+
+impl Bot for MctsBot {
+    fn pick(&mut self, universe: &Universe, color: &Color) -> Option<Coords> {
+        let deadline = now_ms() + self.budget_ms;
+        let root_candidates = candidates(universe, color, &mut self.rng, self.width);
+        if root_candidates.is_empty() {
+            return None;
+        }
+        self.nodes.clear();
+        self.nodes.push(Node::new(usize::MAX, root_candidates));
+        let mut sim = self.scratch.take().unwrap_or_else(|| universe.clone());
+        let mut path = vec![];
+        let mut playouts = 0;
+        while playouts < self.max_playouts && (playouts == 0 || now_ms() < deadline) {
+            sim.clone_from(universe);
+            path.clear();
+            path.push(0);
+            let mut node = 0;
+            let mut to_move = color.clone();
+            while !sim.is_finished() {
+                if let Some(pick) = self.nodes[node].untried.pop() {
+                    Self::play(&mut sim, &to_move, pick);
+                    to_move = to_move.opponent();
+                    let untried = if sim.is_finished() {
+                        vec![]
+                    } else {
+                        candidates(&sim, &to_move, &mut self.rng, self.width)
+                    };
+                    self.nodes.push(Node::new(pick, untried));
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.push(child);
+                    path.push(child);
+                    break;
+                }
+                if self.nodes[node].children.is_empty() {
+                    break;
+                }
+                node = self.select(node);
+                Self::play(&mut sim, &to_move, self.nodes[node].pick);
+                to_move = to_move.opponent();
+                path.push(node);
+            }
+            let result = self.rollout(&mut sim, to_move, color);
+            // odd depths were reached by a pick of the searching color
+            for (depth, &n) in path.iter().enumerate() {
+                self.nodes[n].visits += 1;
+                self.nodes[n].wins += if depth % 2 == 1 { result } else { 1. - result };
+            }
+            playouts += 1;
+        }
+        self.scratch = Some(sim);
+
+        let best = *self.nodes[0]
+            .children
+            .iter()
+            .max_by_key(|&&child| self.nodes[child].visits)?;
+        let best = &self.nodes[best];
+        self.stats = Some(SearchStats {
+            playouts,
+            win_rate: best.wins / best.visits as f64,
+        });
+        Some(universe.get_coords(best.pick))
+    }
+
+    fn stats(&self) -> Option<SearchStats> {
+        self.stats.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bots_pick_free_cells() {
+        let uni = Universe::from_map("#.#\n.#.\n#.#").unwrap();
+        for difficulty in Difficulty::ALL {
+            let mut bot = difficulty.bot(7);
+            for _ in 0..3 {
+                let coords = bot.pick(&uni, &Color::Red).unwrap();
+                let idx = uni.get_index(coords).unwrap();
+                assert_eq!(uni.get_cells()[idx], Cell::Empty);
+            }
+        }
+    }
+
+    #[test]
+    fn mcts_bot_reports_its_search() {
+        let uni = Universe::from_map("......\n......\n......\n......").unwrap();
+        let mut bot = MctsBot::new(3, 1e9).with_max_playouts(200);
+        assert!(bot.stats().is_none());
+        let coords = bot.pick(&uni, &Color::Red).unwrap();
+        assert_eq!(uni.get_cells()[uni.get_index(coords).unwrap()], Cell::Empty);
+        let stats = bot.stats().unwrap();
+        assert_eq!(stats.playouts, 200);
+        assert!((0. ..=1.).contains(&stats.win_rate));
+    }
+
+    #[test]
+    fn greedy_bot_prefers_open_space() {
+        let uni = Universe::from_map("#.##\n#...\n##..").unwrap();
+        let mut bot = GreedyBot::new(1);
+        assert_eq!(bot.pick(&uni, &Color::Blue), Some((2, 1)));
+    }
+}
@@ -0,0 +1,187 @@
+//! Chat between players, one channel for the lobby and one for each
+//! universe. The server keeps the channels and hands out their history with
+//! the lobby list and the universe, the clients post with
+//! `POST /api/usernames/chat` and `POST /api/universe/chat`.
+
+use crate::constants::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub author: String,
+    pub text: String,
+    /// one of `EMOTES`, shown bigger than typed text
+    pub emote: bool,
+}
+
+/// What a client sends to post in a channel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatPost {
+    pub text: String,
+    pub emote: bool,
+}
+
+/// Words masked in typed messages.
+const BLOCKED_WORDS: [&str; 6] = ["fuck", "shit", "bitch", "cunt", "asshole", "bastard"];
+
+/// One chat channel with its newest `CHAT_HISTORY` messages.
+#[derive(Debug, Clone, Default)]
+pub struct Chat {
+    messages: VecDeque<ChatMessage>,
+    /// times each sender posted within the last `CHAT_WINDOW_MS`, only for
+    /// senders that did
+    recent: HashMap<String, VecDeque<f64>>,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message `sender` posts under the name `author` at `now`
+    /// milliseconds. The sender identifies the poster for the rate limit,
+    /// as display names can be made up. Typed text is trimmed and has blocked words
+    /// masked; emotes have to be one of `EMOTES`. Fails on empty or overlong
+    /// messages and when the sender already posted `CHAT_BURST` times within
+    /// `CHAT_WINDOW_MS`.
+    pub fn post(
+        &mut self,
+        sender: &str,
+        author: &str,
+        post: &ChatPost,
+        now: f64,
+    ) -> Result<(), String> {
+        let text = post.text.trim();
+        if post.emote && !EMOTES.contains(&text) {
+            return Err(format!("unknown emote {}", text));
+        }
+        if text.is_empty() {
+            return Err("empty message".into());
+        }
+        if text.chars().count() > CHAT_MAX_LEN {
+            return Err(format!(
+                "messages are limited to {} characters",
+                CHAT_MAX_LEN
+            ));
+        }
+        self.recent.retain(|_, times| {
+            while times.front().is_some_and(|&t| now - t > CHAT_WINDOW_MS) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let recent = self.recent.entry(sender.to_string()).or_default();
+        if recent.len() >= CHAT_BURST {
+            return Err("too many messages, slow down".into());
+        }
+        recent.push_back(now);
+        self.messages.push_back(ChatMessage {
+            author: author.to_string(),
+            text: censor(text),
+            emote: post.emote,
+        });
+        if self.messages.len() > CHAT_HISTORY {
+            self.messages.pop_front();
+        }
+        Ok(())
+    }
+
+    /// History, oldest first.
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        self.messages.iter().cloned().collect()
+    }
+}
+
+/// Replaces blocked words with asterisks, whatever their case. Only whole
+/// words are masked, not words that merely contain a blocked one.
+pub fn censor(text: &str) -> String {
+    let lower = text.to_lowercase();
+    // lowercasing can change the length of some characters, leave those
+    // texts alone rather than cut them in the wrong place
+    if lower.len() != text.len() {
+        return text.to_string();
+    }
+    let mut censored = text.to_string();
+    for word in BLOCKED_WORDS {
+        for (start, _) in lower.match_indices(word) {
+            let end = start + word.len();
+            let before = lower[..start].chars().next_back();
+            let after = lower[end..].chars().next();
+            if before.is_some_and(char::is_alphanumeric) || after.is_some_and(char::is_alphanumeric)
+            {
+                continue;
+            }
+            censored.replace_range(start..end, &"*".repeat(word.len()));
+        }
+    }
+    censored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> ChatPost {
+        ChatPost {
+            text: text.into(),
+            emote: false,
+        }
+    }
+
+    #[test]
+    fn chat_checks_and_masks_messages() {
+        let mut chat = Chat::new();
+        assert!(chat.post("ann", "ann", &typed("   "), 0.).is_err());
+        assert!(chat
+            .post("ann", "ann", &typed(&"a".repeat(CHAT_MAX_LEN + 1)), 0.)
+            .is_err());
+        let wave = ChatPost {
+            text: "wave".into(),
+            emote: true,
+        };
+        assert!(chat.post("ann", "ann", &wave, 0.).is_err());
+        chat.post("ann", "ann", &typed(" Oh SHIT, nice one "), 0.)
+            .unwrap();
+        assert_eq!(chat.messages()[0].text, "Oh ****, nice one");
+    }
+
+    #[test]
+    fn censor_masks_whole_words_only() {
+        assert_eq!(censor("Shit! shitake"), "****! shitake");
+        assert_eq!(censor("bastards, a BASTARD"), "bastards, a *******");
+        assert_eq!(censor("Scunthorpe"), "Scunthorpe");
+    }
+
+    #[test]
+    fn chat_limits_the_rate() {
+        let mut chat = Chat::new();
+        for i in 0..CHAT_BURST {
+            chat.post("ann", "ann", &typed("hi"), i as f64).unwrap();
+        }
+        assert!(chat.post("ann", "ann", &typed("hi"), 100.).is_err());
+        // others are not held up, and the window moves on
+        chat.post("bob", "bob", &typed("hi"), 100.).unwrap();
+        chat.post("ann", "ann", &typed("hi"), CHAT_WINDOW_MS + 1.)
+            .unwrap();
+        for i in 0..CHAT_HISTORY {
+            chat.post(&i.to_string(), "bob", &typed("hi"), 0.).unwrap();
+        }
+        assert_eq!(chat.messages().len(), CHAT_HISTORY);
+    }
+
+    #[test]
+    fn chat_limits_senders_not_names() {
+        let mut chat = Chat::new();
+        for i in 0..CHAT_BURST {
+            chat.post("s1", &i.to_string(), &typed("hi"), 0.).unwrap();
+        }
+        // a new name does not get around the limit, a namesake is not held up
+        assert!(chat.post("s1", "eve", &typed("hi"), 1.).is_err());
+        chat.post("s2", "0", &typed("hi"), 1.).unwrap();
+        // quiet senders are forgotten
+        chat.post("s3", "ann", &typed("hi"), CHAT_WINDOW_MS + 2.)
+            .unwrap();
+        assert_eq!(chat.recent.len(), 1);
+    }
+}
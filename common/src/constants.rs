@@ -2,14 +2,114 @@
 pub const WIDTH_UNIVERSE: u32 = 32;
 pub const HEIGHT_UNIVERSE: u32 = 32;
 pub const N_NEUTRAL_BLOCKS: u32 = 100;
+// board sizes and the longest turn the creator of a private game can choose
+pub const MIN_UNIVERSE_SIZE: u32 = 8;
+pub const MAX_UNIVERSE_SIZE: u32 = 128;
+pub const MAX_TURN_LENGTH: f64 = 60.;
+// ticks a wall placed with the wall ability stays up
+pub const WALL_LIFETIME: u32 = 6;
+
+// chat
+pub const CHAT_MAX_LEN: usize = 200;
+pub const CHAT_HISTORY: usize = 50;
+// messages one player may post within CHAT_WINDOW_MS
+pub const CHAT_BURST: usize = 5;
+pub const CHAT_WINDOW_MS: f64 = 10_000.;
+pub const EMOTES: [&str; 3] = ["gg", "nice", "oops"];
 
 // display
 pub const WIDTH_CANVAS: u32 = (CELL_SIZE + 1) * WIDTH_UNIVERSE + 1;
 pub const HEIGHT_CANVAS: u32 = (CELL_SIZE + 1) * HEIGHT_UNIVERSE + 1;
 
+// default side of a cell in pixels, players can change it
 pub const CELL_SIZE: u32 = 15;
+pub const MIN_CELL_SIZE: u32 = 6;
+pub const MAX_CELL_SIZE: u32 = 40;
+// how far the board can be zoomed in, zooming out stops at the whole board
+pub const MAX_ZOOM: f64 = 4.;
+pub const ZOOM_STEP: f64 = 1.25;
+// CSS pixels a pressed pointer has to move before it pans instead of clicking
+pub const DRAG_THRESHOLD: f64 = 4.;
+// longer side of the minimap in CSS pixels
+pub const MINIMAP_SIZE: u32 = 160;
 pub const GRID_COLOR: &str = "#CCCC";
 pub const EMPTY_COLOR: &str = "#FFFFFF";
 pub const WALL_COLOR: &str = "#000000";
 pub const BLUE_COLOR: &str = "#0000FF";
 pub const RED_COLOR: &str = "#FF0000";
+pub const OBJECTIVE_COLOR: &str = "#FFD700";
+pub const FORTRESS_COLOR: &str = "#808080";
+pub const PORTAL_COLOR: &str = "#9400D3";
+pub const FERTILE_COLOR: &str = "#228B22";
+pub const ONE_WAY_COLOR: &str = "#555555";
+pub const HINT_COLOR: &str = "#00CED1";
+pub const LEGAL_COLOR: &str = "#32CD32";
+pub const ILLEGAL_COLOR: &str = "#A9A9A9";
+
+/// Colors of the board apart from the players.
+pub struct Theme {
+    /// stored in the player's settings
+    pub name: &'static str,
+    pub label: &'static str,
+    pub empty: &'static str,
+    pub grid: &'static str,
+    pub wall: &'static str,
+    /// shapes of the colour-blind palette
+    pub pattern: &'static str,
+}
+
+pub const LIGHT_THEME: Theme = Theme {
+    name: "light",
+    label: "Light",
+    empty: EMPTY_COLOR,
+    grid: GRID_COLOR,
+    wall: WALL_COLOR,
+    pattern: "#000000",
+};
+pub const DARK_THEME: Theme = Theme {
+    name: "dark",
+    label: "Dark",
+    empty: "#202124",
+    grid: "#3C4043",
+    wall: "#9AA0A6",
+    pattern: "#FFFFFF",
+};
+pub const HIGH_CONTRAST_THEME: Theme = Theme {
+    name: "high-contrast",
+    label: "High contrast",
+    empty: "#000000",
+    grid: "#808080",
+    wall: "#FFFFFF",
+    pattern: "#FFFFFF",
+};
+pub const THEMES: [Theme; 3] = [LIGHT_THEME, DARK_THEME, HIGH_CONTRAST_THEME];
+
+/// Colors the two players are drawn in.
+pub struct Palette {
+    pub red: &'static str,
+    pub blue: &'static str,
+    /// mark cells with a shape as well, for players who cannot tell the
+    /// colors apart
+    pub patterns: bool,
+}
+
+pub const STANDARD_PALETTE: Palette = Palette {
+    red: RED_COLOR,
+    blue: BLUE_COLOR,
+    patterns: false,
+};
+// orange and blue from the Okabe-Ito palette
+pub const COLOR_BLIND_PALETTE: Palette = Palette {
+    red: "#E69F00",
+    blue: "#0072B2",
+    patterns: true,
+};
+
+// milliseconds between clock updates in offline games
+pub const LOCAL_TICK_MS: i32 = 100;
+
+// milliseconds between polls of the universe in games on the server
+pub const POLL_MS: i32 = 100;
+
+// milliseconds newly claimed cells take to grow in
+pub const SPREAD_ANIMATION_MS: f64 = 300.;
@@ -0,0 +1,222 @@
+//! Reading the board ahead. Without further picks the spread is fixed, so
+//! every empty cell goes to the color whose spread gets there first. Picks
+//! are suggested by the empty cells they would win that way.
+
+use crate::bot::free_cells;
+use crate::{cell_of, Action, Cell, Color, Terrain, Universe};
+use std::cmp::Ordering;
+
+impl Universe {
+    /// Ticks the spread of `color` needs to reach every cell, going around
+    /// walls and claimed cells. `u32::MAX` where it never gets. The second
+    /// ring of fertile ground is left out, so this can run a little long.
+    pub fn distances(&self, color: &Color) -> Vec<u32> {
+        let own = cell_of(color);
+        let sources: Vec<usize> = (0..self.cells.len())
+            .filter(|&idx| self.cells[idx] == own)
+            .collect();
+        let mut dist = vec![u32::MAX; self.cells.len()];
+        self.walk_spread(&sources, &mut dist, &mut vec![], |_, _| true);
+        dist
+    }
+
+    /// The color each cell ends up with if no one picks again: claimed cells
+    /// keep their color, empty cells go to the color that reaches them
+    /// first. `Color::None` for ties and cells neither color can reach.
+    /// Capture and decay are not taken into account.
+    pub fn projection(&self) -> Vec<Color> {
+        let red = self.distances(&Color::Red);
+        let blue = self.distances(&Color::Blue);
+        (0..self.cells.len())
+            .map(|idx| match self.cells[idx] {
+                Cell::Red => Color::Red,
+                Cell::Blue => Color::Blue,
+                Cell::Neutral => Color::None,
+                Cell::Empty => match red[idx].cmp(&blue[idx]) {
+                    Ordering::Less => Color::Red,
+                    Ordering::Greater => Color::Blue,
+                    Ordering::Equal => Color::None,
+                },
+            })
+            .collect()
+    }
+
+    /// Cells red and blue end up with by `projection`.
+    pub fn projected_score(&self) -> (u32, u32) {
+        let projection = self.projection();
+        let count = |color| projection.iter().filter(|c| **c == color).count() as u32;
+        (count(Color::Red), count(Color::Blue))
+    }
+
+    /// Cells `color` would hold after `action` and the next tick that it
+    /// would not hold without it. `None` when the action is not allowed
+    /// right now.
+    pub fn preview(&self, color: &Color, action: &Action) -> Option<Vec<usize>> {
+        let mut with = self.clone();
+        if !with.act(color, action).unwrap_or(false) {
+            return None;
+        }
+        // the action already ended the turn, and with it the tick
+        if with.get_tick() == self.get_tick() {
+            with.evolve();
+        }
+        let mut without = self.clone();
+        without.evolve();
+        let own = cell_of(color);
+        Some(
+            (0..self.cells.len())
+                .filter(|&idx| with.cells[idx] == own && without.cells[idx] != own)
+                .collect(),
+        )
+    }
+
+    /// How much territory a pick on each cell would win `color`, counted in
+    /// cells, zero where it cannot pick. A cell counts as won when the
+    /// color's spread gets there first and as half won when both colors
+    /// arrive at the same time.
+    pub fn hint_gains(&self, color: &Color) -> Vec<f32> {
+        let own = self.distances(color);
+        let opp = self.distances(&color.opponent());
+        let value = |mine: u32, theirs: u32| match mine.cmp(&theirs) {
+            Ordering::Less => 1.,
+            Ordering::Equal if mine != u32::MAX => 0.5,
+            _ => 0.,
+        };
+        let mut gains = vec![0.; self.cells.len()];
+        let mut dist = vec![u32::MAX; self.cells.len()];
+        let mut touched = vec![];
+        for pick in free_cells(self) {
+            // a cell further out can only gain through cells that gained
+            self.walk_spread(&[pick], &mut dist, &mut touched, |idx, d| {
+                d < own[idx] && d <= opp[idx]
+            });
+            gains[pick] = touched
+                .iter()
+                .map(|&idx| value(dist[idx], opp[idx]) - value(own[idx], opp[idx]))
+                .sum();
+            for &idx in touched.iter() {
+                dist[idx] = u32::MAX;
+            }
+            touched.clear();
+        }
+        gains
+    }
+
+    /// Spreads from `sources` through empty cells like `evolve` would:
+    /// fortresses take a tick longer and portals pass the spread on at
+    /// once. A cell is only entered while `keep(cell, distance)` holds.
+    /// Leaves the distances in `dist` and every cell that got one in
+    /// `touched`.
+    fn walk_spread(
+        &self,
+        sources: &[usize],
+        dist: &mut [u32],
+        touched: &mut Vec<usize>,
+        keep: impl Fn(usize, u32) -> bool,
+    ) {
+        let mut buckets: Vec<Vec<usize>> = vec![sources.to_vec()];
+        for &idx in sources {
+            dist[idx] = 0;
+            touched.push(idx);
+        }
+        let mut d = 0;
+        while d < buckets.len() {
+            for from in std::mem::take(&mut buckets[d]) {
+                if dist[from] < d as u32 {
+                    continue;
+                }
+                for next in self.spread_neighbours(from) {
+                    if self.cells[next] != Cell::Empty {
+                        continue;
+                    }
+                    let step = if self.terrain[next] == Terrain::Fortress {
+                        2
+                    } else {
+                        1
+                    };
+                    let partner = match self.terrain[next] {
+                        Terrain::Portal(p) if self.cells[p] == Cell::Empty => Some(p),
+                        _ => None,
+                    };
+                    let nd = d as u32 + step;
+                    for idx in std::iter::once(next).chain(partner) {
+                        if nd >= dist[idx] || !keep(idx, nd) {
+                            continue;
+                        }
+                        if dist[idx] == u32::MAX {
+                            touched.push(idx);
+                        }
+                        dist[idx] = nd;
+                        if buckets.len() <= nd as usize {
+                            buckets.resize(nd as usize + 1, vec![]);
+                        }
+                        buckets[nd as usize].push(idx);
+                    }
+                }
+            }
+            d += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSettings, GameSettings};
+
+    #[test]
+    fn hints_rate_contested_cells() {
+        let mut uni = Universe::from_map(".....").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (4, 0)).unwrap();
+        assert_eq!(uni.distances(&Color::Red), vec![0, 1, 2, 3, u32::MAX]);
+        assert_eq!(uni.hint_gains(&Color::Red), vec![0., 0.5, 1., 1.5, 0.]);
+    }
+
+    #[test]
+    fn preview_shows_the_next_spread() {
+        let mut uni = Universe::from_map("....\n.#..").unwrap();
+        uni.set_cell(&Cell::Blue, (3, 1)).unwrap();
+        assert_eq!(
+            uni.preview(&Color::Red, &Action::Pick((0, 0))),
+            Some(vec![0, 1, 4])
+        );
+        assert_eq!(uni.preview(&Color::Red, &Action::Pick((1, 1))), None);
+        assert_eq!(uni.preview(&Color::Blue, &Action::Pick((0, 0))), None);
+    }
+
+    #[test]
+    fn preview_ticks_once_when_the_pick_ends_the_turn() {
+        let settings = GameSettings {
+            clock: ClockSettings {
+                pick_ends_turn: true,
+                ..ClockSettings::default()
+            },
+            ..GameSettings::default()
+        };
+        let uni = Universe::from_map("......")
+            .unwrap()
+            .with_settings(settings);
+        assert_eq!(
+            uni.preview(&Color::Red, &Action::Pick((0, 0))),
+            Some(vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn projection_goes_around_walls() {
+        let mut uni = Universe::from_map("...\n.#.\n...\n...").unwrap();
+        uni.set_cell(&Cell::Red, (1, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (1, 3)).unwrap();
+        use Color::{Blue as B, None as N, Red as R};
+        #[rustfmt::skip]
+        let expected = vec![
+            R, R, R,
+            R, N, R,
+            B, B, B,
+            B, B, B,
+        ];
+        assert_eq!(uni.projection(), expected);
+        assert_eq!(uni.projected_score(), (5, 6));
+    }
+}
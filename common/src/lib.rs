@@ -1,4 +1,16 @@
+pub mod api;
+pub mod bot;
+pub mod chat;
+pub mod constants;
+pub mod hints;
+pub mod lobby;
+pub mod map;
+
+use chat::ChatMessage;
+use constants::*;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Color {
@@ -7,6 +19,451 @@ pub enum Color {
     None,
 }
 
+impl Color {
+    /// Parses the lowercase color names used in sessions and `ColorSender`.
+    pub fn from_name(name: &str) -> Color {
+        match name {
+            "red" => Color::Red,
+            "blue" => Color::Blue,
+            _ => Color::None,
+        }
+    }
+
+    pub fn opponent(&self) -> Color {
+        match self {
+            Color::Red => Color::Blue,
+            Color::Blue => Color::Red,
+            Color::None => Color::None,
+        }
+    }
+}
+
+/// How a finished game was decided. The winning color is stored with the
+/// outcome, so `Forfeit(Color::Red)` means red won because blue gave up.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GameOutcome {
+    Territory(Color),
+    Connected(Color),
+    Objective(Color),
+    Forfeit(Color),
+    Draw,
+    Abandoned,
+}
+
+impl fmt::Display for GameOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameOutcome::Territory(c) => write!(f, "{:?} wins by territory", c),
+            GameOutcome::Connected(c) => write!(f, "{:?} wins by connecting two edges", c),
+            GameOutcome::Objective(c) => write!(f, "{:?} wins by capturing the objective", c),
+            GameOutcome::Forfeit(c) => write!(f, "{:?} wins by forfeit", c),
+            GameOutcome::Draw => write!(f, "Draw"),
+            GameOutcome::Abandoned => write!(f, "Game abandoned"),
+        }
+    }
+}
+
+/// How the engine decides the winner. Every rule falls back to comparing
+/// territory once the board is full or nothing can spread anymore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum VictoryRule {
+    /// more cells than the opponent, decided early once the spread settles it
+    #[default]
+    TerritoryMajority,
+    /// first color to own the given percentage of the board
+    BoardShare(u32),
+    /// first color whose cells connect two opposite edges
+    ConnectEdges,
+    /// first color to spread onto the marked cell, it cannot be picked
+    Objective(Coords),
+    /// most cells after the given number of ticks
+    ScoreAfter(u32),
+}
+
+impl fmt::Display for VictoryRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VictoryRule::TerritoryMajority => write!(f, "Claim more cells than your opponent"),
+            VictoryRule::BoardShare(p) => write!(f, "First to own {}% of the board", p),
+            VictoryRule::ConnectEdges => write!(f, "Connect two opposite edges"),
+            VictoryRule::Objective(_) => write!(f, "Capture the marked cell"),
+            VictoryRule::ScoreAfter(n) => write!(f, "Most cells after {} ticks", n),
+        }
+    }
+}
+
+/// Parses rules written as `territory`, `share:60`, `edges`, `objective`,
+/// `objective:10,12` or `score:200`. A bare `objective` marks the center.
+/// Shares have to be between 1 and 100 percent; whether the objective is on
+/// the board is up to `GameSettings::check`.
+impl FromStr for VictoryRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = s.split_once(':').unwrap_or((s, ""));
+        let number = |arg: &str| {
+            arg.parse::<u32>()
+                .map_err(|_| format!("bad number in {}", s))
+        };
+        match name {
+            "territory" => Ok(VictoryRule::TerritoryMajority),
+            "share" => match number(arg)? {
+                share @ 1..=100 => Ok(VictoryRule::BoardShare(share)),
+                _ => Err(format!("share has to be a percentage in {}", s)),
+            },
+            "edges" => Ok(VictoryRule::ConnectEdges),
+            "objective" if arg.is_empty() => Ok(VictoryRule::Objective((
+                WIDTH_UNIVERSE as usize / 2,
+                HEIGHT_UNIVERSE as usize / 2,
+            ))),
+            "objective" => {
+                let (x, y) = arg.split_once(',').ok_or(format!("bad coords in {}", s))?;
+                Ok(VictoryRule::Objective((
+                    number(x)? as usize,
+                    number(y)? as usize,
+                )))
+            }
+            "score" => Ok(VictoryRule::ScoreAfter(number(arg)?)),
+            _ => Err(format!("unknown victory rule {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ClockMode {
+    /// every turn lasts the full turn length
+    Fixed,
+    /// each player has a bank of seconds that only runs during their own
+    /// turn, `increment` is added after every pick. An empty bank loses.
+    Bank { initial: f64, increment: f64 },
+}
+
+/// Parses `fixed`, `bank:60` or `bank:60+2` (bank of 60s, 2s increment).
+impl FromStr for ClockMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |arg: &str| {
+            arg.parse::<f64>()
+                .map_err(|_| format!("bad number in {}", s))
+        };
+        match s.split_once(':') {
+            None if s == "fixed" => Ok(ClockMode::Fixed),
+            Some(("bank", arg)) => {
+                let (initial, increment) = arg.split_once('+').unwrap_or((arg, "0"));
+                Ok(ClockMode::Bank {
+                    initial: number(initial)?,
+                    increment: number(increment)?,
+                })
+            }
+            _ => Err(format!("unknown clock mode {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClockSettings {
+    pub mode: ClockMode,
+    /// longest a turn can last in seconds
+    pub turn_length: f64,
+    /// a pick hands the turn over right away, always the case in bank mode
+    pub pick_ends_turn: bool,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self {
+            mode: ClockMode::Fixed,
+            turn_length: 2.,
+            pick_ends_turn: false,
+        }
+    }
+}
+
+/// Turn state of a running game, sent to the clients with the universe.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clock {
+    pub turn: Color,
+    /// seconds since the current turn started
+    pub elapsed: f64,
+    pub picked: bool,
+    pub red_bank: f64,
+    pub blue_bank: f64,
+}
+
+impl Clock {
+    pub fn new(settings: &ClockSettings) -> Self {
+        let bank = match settings.mode {
+            ClockMode::Fixed => 0.,
+            ClockMode::Bank { initial, .. } => initial,
+        };
+        Self {
+            turn: Color::Red,
+            elapsed: 0.,
+            picked: false,
+            red_bank: bank,
+            blue_bank: bank,
+        }
+    }
+
+    pub fn bank(&self, color: &Color) -> f64 {
+        match color {
+            Color::Red => self.red_bank,
+            Color::Blue => self.blue_bank,
+            Color::None => 0.,
+        }
+    }
+
+    fn bank_mut(&mut self) -> &mut f64 {
+        match self.turn {
+            Color::Blue => &mut self.blue_bank,
+            _ => &mut self.red_bank,
+        }
+    }
+}
+
+/// Rules of a single game, fixed when its universe is created.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameSettings {
+    pub victory_rule: VictoryRule,
+    pub clock: ClockSettings,
+    /// charges each player starts with
+    pub abilities: Abilities,
+    /// groups enclosed by the opponent flip to the opponent's color
+    pub capture: bool,
+    /// claimed cells age and may fall back to empty
+    pub decay: Option<DecaySettings>,
+    pub neighbourhood: Neighbourhood,
+    /// players may show suggested picks, off for ranked games
+    pub hints: bool,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            victory_rule: VictoryRule::default(),
+            clock: ClockSettings::default(),
+            abilities: Abilities::default(),
+            capture: false,
+            decay: None,
+            neighbourhood: Neighbourhood::default(),
+            hints: true,
+        }
+    }
+}
+
+impl GameSettings {
+    /// Refuses rules that cannot be played on a `width` by `height` board:
+    /// shares outside 1 to 100 percent, an objective off the board, turns
+    /// or banks that do not last a positive number of seconds, negative
+    /// increments, decay without a lifespan or asking for more support than
+    /// a cell has neighbours, and decay without a `ScoreAfter` rule, as
+    /// decay games never settle early.
+    pub fn check(&self, width: usize, height: usize) -> Result<(), String> {
+        match self.victory_rule {
+            VictoryRule::BoardShare(p) if p == 0 || p > 100 => {
+                return Err("the share has to be a percentage".into())
+            }
+            VictoryRule::Objective((x, y)) if x >= width || y >= height => {
+                return Err("the objective has to be on the board".into())
+            }
+            VictoryRule::ScoreAfter(_) => {}
+            _ if self.decay.is_some() => {
+                return Err("decay needs a score: victory rule to end".into())
+            }
+            _ => {}
+        }
+        let turn_length = self.clock.turn_length;
+        if !(turn_length.is_finite() && turn_length > 0.) {
+            return Err("turns have to last a positive number of seconds".into());
+        }
+        if let ClockMode::Bank { initial, increment } = self.clock.mode {
+            if !(initial.is_finite() && initial > 0.) {
+                return Err("the bank has to hold a positive number of seconds".into());
+            }
+            if !(increment.is_finite() && increment >= 0.) {
+                return Err("the increment cannot be negative".into());
+            }
+        }
+        if let Some(decay) = &self.decay {
+            let neighbours = match self.neighbourhood {
+                Neighbourhood::VonNeumann => 4,
+                Neighbourhood::Moore => 8,
+            };
+            if decay.lifespan == 0 {
+                return Err("cells have to live for at least a tick".into());
+            }
+            if decay.support > neighbours {
+                return Err(format!("cells have at most {} neighbours", neighbours));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which cells count as neighbours, for spreading as well as for regions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Neighbourhood {
+    /// the four orthogonal cells
+    #[default]
+    VonNeumann,
+    /// the diagonal cells as well
+    Moore,
+}
+
+impl fmt::Display for Neighbourhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Neighbourhood::VonNeumann => write!(f, "von-neumann"),
+            Neighbourhood::Moore => write!(f, "moore"),
+        }
+    }
+}
+
+impl FromStr for Neighbourhood {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "von-neumann" | "4" => Ok(Neighbourhood::VonNeumann),
+            "moore" | "8" => Ok(Neighbourhood::Moore),
+            _ => Err(format!("unknown neighbourhood {}", s)),
+        }
+    }
+}
+
+/// Ages are counted in ticks since a cell was claimed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DecaySettings {
+    /// a cell spreads for this many ticks after it was claimed
+    pub spread_age: u32,
+    /// from this age on a cell needs support to survive
+    pub lifespan: u32,
+    /// neighbours of the same color an old cell needs
+    pub support: u32,
+}
+
+impl Default for DecaySettings {
+    fn default() -> Self {
+        Self {
+            spread_age: 4,
+            lifespan: 16,
+            support: 2,
+        }
+    }
+}
+
+impl FromStr for DecaySettings {
+    type Err = String;
+
+    /// Parses `spread_age,lifespan,support`, e.g. "4,16,2".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers = s
+            .split(',')
+            .map(|n| n.trim().parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| format!("bad number in {}", s))?;
+        match numbers[..] {
+            [spread_age, lifespan, support] => Ok(DecaySettings {
+                spread_age,
+                lifespan,
+                support,
+            }),
+            _ => Err(format!("expected spread_age,lifespan,support, got {}", s)),
+        }
+    }
+}
+
+/// What a player does with their turn.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Action {
+    Pick(Coords),
+    /// puts a wall on an empty cell for `WALL_LIFETIME` ticks
+    Wall(Coords),
+    /// turns a neutral cell into an empty one
+    Clear(Coords),
+    /// stops an enemy frontier cell from spreading on the next tick
+    Freeze(Coords),
+    /// claims two empty cells at once
+    DoublePick(Coords, Coords),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Ability {
+    Wall,
+    Clear,
+    Freeze,
+    DoublePick,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 4] = [
+        Ability::Wall,
+        Ability::Clear,
+        Ability::Freeze,
+        Ability::DoublePick,
+    ];
+}
+
+impl fmt::Display for Ability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ability::Wall => write!(f, "Wall"),
+            Ability::Clear => write!(f, "Clear"),
+            Ability::Freeze => write!(f, "Freeze"),
+            Ability::DoublePick => write!(f, "Double pick"),
+        }
+    }
+}
+
+/// Charges left per ability.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Abilities {
+    pub wall: u32,
+    pub clear: u32,
+    pub freeze: u32,
+    pub double_pick: u32,
+}
+
+impl Abilities {
+    pub fn left(&self, ability: Ability) -> u32 {
+        match ability {
+            Ability::Wall => self.wall,
+            Ability::Clear => self.clear,
+            Ability::Freeze => self.freeze,
+            Ability::DoublePick => self.double_pick,
+        }
+    }
+
+    fn take(&mut self, ability: Ability) {
+        match ability {
+            Ability::Wall => self.wall -= 1,
+            Ability::Clear => self.clear -= 1,
+            Ability::Freeze => self.freeze -= 1,
+            Ability::DoublePick => self.double_pick -= 1,
+        }
+    }
+}
+
+/// What spectators may use.
+const NO_ABILITIES: Abilities = Abilities {
+    wall: 0,
+    clear: 0,
+    freeze: 0,
+    double_pick: 0,
+};
+
+impl Default for Abilities {
+    fn default() -> Self {
+        Self {
+            wall: 2,
+            clear: 2,
+            freeze: 2,
+            double_pick: 1,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub name: String,
@@ -27,10 +484,16 @@ pub struct ColorSender {
     pub value: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct PreGameData {
+    pub ready: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct UserList {
     pub users: Vec<User>,
     pub n_users: u32,
+    pub chat: Vec<ChatMessage>,
 }
 
 impl UserList {
@@ -38,10 +501,18 @@ impl UserList {
         UserList {
             users: vec![],
             n_users: 0,
+            chat: vec![],
         }
     }
 }
 
+/// What players poll during a game: the universe and its chat.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameUpdate {
+    pub universe: Universe,
+    pub chat: Vec<ChatMessage>,
+}
+
 #[derive(Debug)]
 pub struct CellReadError;
 
@@ -70,26 +541,77 @@ impl Default for Cell {
     }
 }
 
-pub const CELL_SIZE: u32 = 15;
-pub const GRID_COLOR: &str = "#CCCC";
-pub const EMPTY_COLOR: &str = "#FFFFFF";
-pub const WALL_COLOR: &str = "#000000";
-pub const BLUE_COLOR: &str = "#0000FF";
-pub const RED_COLOR: &str = "#FF0000";
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
 
-pub const WIDTH_UNIVERSE: u32 = 32;
-pub const HEIGHT_UNIVERSE: u32 = 32;
-pub const N_NEUTRAL_BLOCKS: u32 = 100;
+impl Direction {
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+        }
+    }
+}
 
-pub const WIDTH_CANVAS: u32 = (CELL_SIZE + 1) * WIDTH_UNIVERSE + 1;
-pub const HEIGHT_CANVAS: u32 = (CELL_SIZE + 1) * HEIGHT_UNIVERSE + 1;
+/// Ground a cell lies on. It stays the same when the cell gets claimed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Terrain {
+    #[default]
+    Plain,
+    /// needs two ticks of spread to be captured
+    Fortress,
+    /// claims are passed on to the linked portal at the given index
+    Portal(usize),
+    /// spreads two rings at once
+    Fertile,
+    /// one-way wall, spread can only enter it moving in this direction
+    OneWay(Direction),
+}
 
 pub type Coords = (usize, usize);
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Who can still claim a connected region of empty cells by spreading.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RegionOwner {
+    Red,
+    Blue,
+    Contested,
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub cells: Vec<usize>,
+    pub owner: RegionOwner,
+}
+
+/// Connected cells of one color. A group is free while it borders an
+/// empty cell.
+struct Group {
+    cells: Vec<usize>,
+    color: Cell,
+    free: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Universe {
     cells: Vec<Cell>,
+    terrain: Vec<Terrain>,
+    /// ticks since each cell was claimed, only kept up in decay mode
+    ages: Vec<u32>,
     active_cells: Vec<(Cell, Coords)>,
+    sieges: Vec<(Cell, Coords)>,
+    frozen: Vec<(Cell, Coords)>,
+    temporary_walls: Vec<(usize, u32)>,
+    red_abilities: Abilities,
+    blue_abilities: Abilities,
     width: usize,
     height: usize,
     n_empty: u32,
@@ -97,10 +619,95 @@ pub struct Universe {
     n_red: u32,
     n_blue: u32,
     finished: bool,
-    timer: f64,
+    outcome: Option<GameOutcome>,
+    settings: GameSettings,
+    tick: u32,
+    clock: Clock,
+    /// buffer `evolve` collects the next frontier in
+    #[serde(skip)]
+    spare_cells: Vec<(Cell, Coords)>,
+    /// buffer for the cells a fertile cell reaches in its first ring
+    #[serde(skip)]
+    spare_reached: Vec<usize>,
 
     pub red_player_connected: bool,
     pub blue_player_connected: bool,
+    pub draw_offer: Color,
+    /// chance the computer opponent gives itself to win, if it has one
+    pub bot_estimate: Option<f64>,
+}
+
+impl Clone for Universe {
+    fn clone(&self) -> Self {
+        let mut universe = Universe::from_parts(self.width, self.height, vec![], vec![]);
+        universe.clone_from(self);
+        universe
+    }
+
+    /// Reuses the buffers already allocated by `self`, so a simulation can
+    /// reset its scratch universe for every playout without allocating.
+    fn clone_from(&mut self, source: &Self) {
+        self.cells.clone_from(&source.cells);
+        self.terrain.clone_from(&source.terrain);
+        self.ages.clone_from(&source.ages);
+        self.active_cells.clone_from(&source.active_cells);
+        self.sieges.clone_from(&source.sieges);
+        self.frozen.clone_from(&source.frozen);
+        self.temporary_walls.clone_from(&source.temporary_walls);
+        self.red_abilities.clone_from(&source.red_abilities);
+        self.blue_abilities.clone_from(&source.blue_abilities);
+        self.width = source.width;
+        self.height = source.height;
+        self.n_empty = source.n_empty;
+        self.n_neutral = source.n_neutral;
+        self.n_red = source.n_red;
+        self.n_blue = source.n_blue;
+        self.finished = source.finished;
+        self.outcome.clone_from(&source.outcome);
+        self.settings.clone_from(&source.settings);
+        self.tick = source.tick;
+        self.clock.clone_from(&source.clock);
+        self.red_player_connected = source.red_player_connected;
+        self.blue_player_connected = source.blue_player_connected;
+        self.draw_offer.clone_from(&source.draw_offer);
+        self.bot_estimate = source.bot_estimate;
+    }
+}
+
+/// Indices of up to eight neighbouring cells. They live on the stack, so
+/// looking around a cell does not allocate.
+#[derive(Debug, Clone, Copy)]
+struct Neighbours {
+    cells: [usize; 8],
+    len: usize,
+    next: usize,
+}
+
+impl Neighbours {
+    fn new() -> Self {
+        Self {
+            cells: [0; 8],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, idx: usize) {
+        self.cells[self.len] = idx;
+        self.len += 1;
+    }
+}
+
+impl Iterator for Neighbours {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next == self.len {
+            return None;
+        }
+        self.next += 1;
+        Some(self.cells[self.next - 1])
+    }
 }
 
 enum CellWrapper<'a> {
@@ -110,90 +717,764 @@ enum CellWrapper<'a> {
 
 impl Universe {
     pub fn _new_rand(cells: Vec<Cell>, n_empty: u32, n_neutral: u32) -> Self {
-        Universe {
+        let mut uni = Self::from_parts(
+            WIDTH_UNIVERSE as usize,
+            HEIGHT_UNIVERSE as usize,
             cells,
-            active_cells: vec![],
-            width: WIDTH_UNIVERSE as usize,
-            height: HEIGHT_UNIVERSE as usize,
-            n_empty,
-            n_neutral,
-            n_red: 0,
-            n_blue: 0,
-            finished: false,
-            timer: 3.,
-            red_player_connected: false,
-            blue_player_connected: false,
+            vec![],
+        );
+        uni.n_empty = n_empty;
+        uni.n_neutral = n_neutral;
+        uni
+    }
+
+    /// A `width` x `height` board with up to `n_neutral` neutral blocks at
+    /// random places, the same for the same `seed`.
+    pub fn generate(width: usize, height: usize, n_neutral: u32, seed: u64) -> Universe {
+        let mut rng = bot::Rng::new(seed);
+        let mut cells = vec![Cell::Empty; width * height];
+        for _ in 0..n_neutral {
+            let idx = rng.below(cells.len());
+            cells[idx] = Cell::Neutral;
         }
+        Self::from_parts(width, height, cells, vec![])
     }
 
     pub fn new_empty() -> Universe {
+        Self::from_parts(
+            WIDTH_UNIVERSE as usize,
+            HEIGHT_UNIVERSE as usize,
+            Vec::from([Cell::Empty; (WIDTH_UNIVERSE * HEIGHT_UNIVERSE) as usize]),
+            vec![],
+        )
+    }
+
+    /// Builds a universe from a board without claimed cells. Missing terrain
+    /// is filled up with plain ground.
+    pub(crate) fn from_parts(
+        width: usize,
+        height: usize,
+        cells: Vec<Cell>,
+        mut terrain: Vec<Terrain>,
+    ) -> Universe {
+        terrain.resize(cells.len(), Terrain::Plain);
+        let count = |c: Cell| cells.iter().filter(|&&v| v == c).count() as u32;
         Universe {
-            cells: Vec::from([Cell::Empty; (WIDTH_UNIVERSE * HEIGHT_UNIVERSE) as usize]),
+            n_empty: count(Cell::Empty),
+            n_neutral: count(Cell::Neutral),
+            ages: vec![0; cells.len()],
+            cells,
+            terrain,
             active_cells: vec![],
-            width: WIDTH_UNIVERSE as usize,
-            height: HEIGHT_UNIVERSE as usize,
-            n_empty: (WIDTH_UNIVERSE * HEIGHT_UNIVERSE),
-            n_neutral: 0,
+            sieges: vec![],
+            frozen: vec![],
+            temporary_walls: vec![],
+            red_abilities: Abilities::default(),
+            blue_abilities: Abilities::default(),
+            width,
+            height,
             n_red: 0,
             n_blue: 0,
             finished: false,
-            timer: 3.,
+            outcome: None,
+            settings: GameSettings::default(),
+            tick: 0,
+            clock: Clock::new(&ClockSettings::default()),
+            spare_cells: vec![],
+            spare_reached: vec![],
             red_player_connected: false,
             blue_player_connected: false,
+            draw_offer: Color::None,
+            bot_estimate: None,
         }
     }
 
+    /// Plays the universe under `settings`. A wall on the objective is
+    /// cleared, as the objective has to be claimable.
+    ///
+    /// Panics on settings `GameSettings::check` refuses for this board.
+    pub fn with_settings(mut self, settings: GameSettings) -> Self {
+        if let Err(e) = settings.check(self.width, self.height) {
+            panic!("invalid game settings: {}", e);
+        }
+        self.clock = Clock::new(&settings.clock);
+        self.red_abilities = settings.abilities.clone();
+        self.blue_abilities = settings.abilities.clone();
+        self.settings = settings;
+        if let VictoryRule::Objective(coords) = self.settings.victory_rule {
+            let idx = self.get_index(coords).unwrap();
+            self.clear_neutral(idx);
+        }
+        self
+    }
+
+    pub fn get_settings(&self) -> &GameSettings {
+        &self.settings
+    }
+
     pub fn set_cell(&mut self, cell: &Cell, coords: Coords) -> Result<bool, CellReadError> {
-        self._set_cell(CellWrapper::SelfManip, cell, coords)
+        if self.settings.victory_rule == VictoryRule::Objective(coords) {
+            return Ok(false);
+        }
+        let claimed = self._set_cell(CellWrapper::SelfManip, cell, coords)?;
+        if let (true, Terrain::Portal(partner)) =
+            (claimed, self.terrain[self.get_index(coords).unwrap()])
+        {
+            self._set_cell(CellWrapper::SelfManip, cell, self.get_coords(partner))?;
+        }
+        Ok(claimed)
+    }
+
+    /// Claims a cell for `color` if it is that color's turn and it has not
+    /// picked yet this turn. Returns whether the cell was claimed.
+    pub fn pick(&mut self, color: &Color, coords: Coords) -> Result<bool, CellReadError> {
+        self.act(color, &Action::Pick(coords))
+    }
+
+    /// Carries out `color`'s action for this turn. Abilities use up a charge
+    /// and count as the turn's pick. Returns whether the action took effect.
+    pub fn act(&mut self, color: &Color, action: &Action) -> Result<bool, CellReadError> {
+        if *color == Color::None {
+            return Err(CellReadError);
+        }
+        let cell = cell_of(color);
+        if self.finished || self.clock.turn != *color || self.clock.picked {
+            return Ok(false);
+        }
+        let ability = match action {
+            Action::Pick(_) => None,
+            Action::Wall(_) => Some(Ability::Wall),
+            Action::Clear(_) => Some(Ability::Clear),
+            Action::Freeze(_) => Some(Ability::Freeze),
+            Action::DoublePick(..) => Some(Ability::DoublePick),
+        };
+        if let Some(ability) = ability {
+            if self.get_abilities(color).left(ability) == 0 {
+                return Ok(false);
+            }
+        }
+        let done = match *action {
+            Action::Pick(coords) => self.set_cell(&cell, coords)?,
+            Action::Wall(coords) => {
+                let idx = self.get_index(coords).map_err(|_| CellReadError)?;
+                let objective = self.settings.victory_rule == VictoryRule::Objective(coords);
+                if self.cells[idx] == Cell::Empty && !objective {
+                    self.cells[idx] = Cell::Neutral;
+                    self.n_empty -= 1;
+                    self.n_neutral += 1;
+                    self.temporary_walls.push((idx, WALL_LIFETIME));
+                    true
+                } else {
+                    false
+                }
+            }
+            Action::Clear(coords) => {
+                let idx = self.get_index(coords).map_err(|_| CellReadError)?;
+                self.clear_neutral(idx)
+            }
+            Action::Freeze(coords) => {
+                let enemy = (cell_of(&color.opponent()), coords);
+                if self.active_cells.contains(&enemy) {
+                    self.active_cells.retain(|c| *c != enemy);
+                    self.frozen.push(enemy);
+                    true
+                } else {
+                    false
+                }
+            }
+            Action::DoublePick(a, b) => {
+                // both cells have to be claimable, or the charge is kept
+                let pickable = |c: Coords| match self.get_index(c) {
+                    Ok(idx) => {
+                        self.cells[idx] == Cell::Empty
+                            && self.settings.victory_rule != VictoryRule::Objective(c)
+                    }
+                    Err(_) => false,
+                };
+                if a != b && pickable(a) && pickable(b) {
+                    self.set_cell(&cell, a)? | self.set_cell(&cell, b)?
+                } else {
+                    false
+                }
+            }
+        };
+        if done {
+            if let Some(ability) = ability {
+                match color {
+                    Color::Blue => self.blue_abilities.take(ability),
+                    _ => self.red_abilities.take(ability),
+                }
+            }
+            self.clock.picked = true;
+            if self.settings.clock.pick_ends_turn || self.settings.clock.mode != ClockMode::Fixed {
+                self.end_turn();
+            }
+        }
+        Ok(done)
+    }
+
+    /// Turns a neutral cell into an empty one. Colored neighbours go back on
+    /// the frontier so they can spread into the gap.
+    fn clear_neutral(&mut self, idx: usize) -> bool {
+        if self.cells[idx] != Cell::Neutral {
+            return false;
+        }
+        self.cells[idx] = Cell::Empty;
+        self.n_neutral -= 1;
+        self.n_empty += 1;
+        self.temporary_walls.retain(|w| w.0 != idx);
+        for neighbour_idx in self.get_neighbours(self.get_coords(idx)) {
+            let neighbour = self.cells[neighbour_idx];
+            let entry = (neighbour, self.get_coords(neighbour_idx));
+            if (neighbour == Cell::Red || neighbour == Cell::Blue)
+                && !self.active_cells.contains(&entry)
+            {
+                self.active_cells.push(entry);
+            }
+        }
+        true
+    }
+
+    /// Runs the clock for `dt` seconds. When the turn is over the universe
+    /// evolves and the other color is to move. Returns whether the turn
+    /// changed.
+    pub fn advance_clock(&mut self, dt: f64) -> bool {
+        if self.finished {
+            return false;
+        }
+        self.clock.elapsed += dt;
+        if let ClockMode::Bank { .. } = self.settings.clock.mode {
+            let bank = self.clock.bank_mut();
+            *bank -= dt;
+            if *bank <= 0. {
+                *bank = 0.;
+                self.finish(GameOutcome::Forfeit(self.clock.turn.opponent()));
+                return false;
+            }
+        }
+        if self.clock.elapsed >= self.settings.clock.turn_length {
+            self.end_turn();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn end_turn(&mut self) {
+        if let ClockMode::Bank { increment, .. } = self.settings.clock.mode {
+            if self.clock.picked {
+                *self.clock.bank_mut() += increment;
+            }
+        }
+        // the opponent let their turn pass without taking the offer
+        if self.draw_offer == self.clock.turn.opponent() {
+            self.draw_offer = Color::None;
+        }
+        self.evolve();
+        self.clock.turn = self.clock.turn.opponent();
+        self.clock.elapsed = 0.;
+        self.clock.picked = false;
     }
 
     pub fn evolve(&mut self) {
-        let mut next_cells = vec![];
+        for wall in self.temporary_walls.iter_mut() {
+            wall.1 -= 1;
+        }
+        while let Some(pos) = self.temporary_walls.iter().position(|w| w.1 == 0) {
+            let idx = self.temporary_walls.remove(pos).0;
+            self.clear_neutral(idx);
+        }
+        let mut next_cells = std::mem::take(&mut self.spare_cells);
+        next_cells.clear();
+        // fortresses reached during the last tick fall now
+        for (cell, coords) in std::mem::take(&mut self.sieges) {
+            let _ = self._set_cell(CellWrapper::Extern(&mut next_cells), &cell, coords);
+        }
+        let spread_age = self.settings.decay.as_ref().map(|d| d.spread_age);
         while let Some(cell) = self.active_cells.pop() {
-            for neighbour_idx in self.get_neighbours(cell.1) {
-                if self.cells[neighbour_idx] == Cell::Empty {
-                    if let Ok(_) = self._set_cell(
-                        CellWrapper::Extern(&mut next_cells),
-                        &cell.0.clone(),
-                        self.get_coords(neighbour_idx),
-                    ) {};
+            let idx = self.get_index(cell.1).unwrap();
+            if spread_age.is_some_and(|age| self.ages[idx] > age) {
+                continue;
+            }
+            if self.terrain[idx] != Terrain::Fertile {
+                for neighbour_idx in self.spread_neighbours(idx) {
+                    self.spread_to(&mut next_cells, cell.0, neighbour_idx);
+                }
+                continue;
+            }
+            // fertile cells spread on from the cells they just claimed
+            let mut reached = std::mem::take(&mut self.spare_reached);
+            reached.clear();
+            for neighbour_idx in self.spread_neighbours(idx) {
+                if self.spread_to(&mut next_cells, cell.0, neighbour_idx) {
+                    reached.push(neighbour_idx);
+                }
+            }
+            for &from in &reached {
+                for neighbour_idx in self.spread_neighbours(from) {
+                    self.spread_to(&mut next_cells, cell.0, neighbour_idx);
+                }
+            }
+            self.spare_reached = reached;
+        }
+        // the emptied frontier keeps its capacity for the next tick
+        self.spare_cells = std::mem::replace(&mut self.active_cells, next_cells);
+        // frozen cells sat out this tick and spread on the next one
+        self.active_cells.append(&mut self.frozen);
+        if self.settings.capture {
+            self.capture();
+        }
+        if let Some(decay) = self.settings.decay.clone() {
+            self.decay(&decay);
+        }
+        self.tick += 1;
+        self.check_victory();
+    }
+
+    fn check_victory(&mut self) {
+        let (n_red, n_blue) = (self.n_red, self.n_blue);
+        let outcome = match self.settings.victory_rule {
+            VictoryRule::TerritoryMajority => None,
+            VictoryRule::BoardShare(percent) => {
+                let needed = self.cells.len() as u32 * percent / 100;
+                if n_red.max(n_blue) >= needed {
+                    Some(Self::territory_outcome(n_red, n_blue))
+                } else {
+                    None
+                }
+            }
+            VictoryRule::ConnectEdges => {
+                match (
+                    self.connects_edges(Cell::Red),
+                    self.connects_edges(Cell::Blue),
+                ) {
+                    (true, false) => Some(GameOutcome::Connected(Color::Red)),
+                    (false, true) => Some(GameOutcome::Connected(Color::Blue)),
+                    (true, true) => Some(Self::territory_outcome(n_red, n_blue)),
+                    (false, false) => None,
+                }
+            }
+            VictoryRule::Objective(coords) => match self.get_index(coords).map(|i| self.cells[i]) {
+                Ok(Cell::Red) => Some(GameOutcome::Objective(Color::Red)),
+                Ok(Cell::Blue) => Some(GameOutcome::Objective(Color::Blue)),
+                _ => None,
+            },
+            VictoryRule::ScoreAfter(ticks) if self.tick >= ticks => {
+                Some(Self::territory_outcome(n_red, n_blue))
+            }
+            VictoryRule::ScoreAfter(_) => None,
+        };
+        if let Some(outcome) = outcome {
+            self.finish(outcome);
+        } else if self.n_empty == 0 && self.temporary_walls.is_empty() {
+            self.finish(Self::territory_outcome(n_red, n_blue));
+        } else if self.settings.decay.is_none() {
+            // Stop early once no pick, spread or expiring wall can change the
+            // result. Claimed cells stay claimed, so a color holding more
+            // than the other could still get has won, unless captures can
+            // turn cells around.
+            let open = self.n_empty + self.temporary_walls.len() as u32;
+            let decided = self.settings.victory_rule == VictoryRule::TerritoryMajority
+                && !self.settings.capture
+                && n_red.max(n_blue) > n_red.min(n_blue) + open;
+            // the objective cannot be picked, so it may be the last empty cell
+            let objective_left = match self.settings.victory_rule {
+                VictoryRule::Objective(coords) => self
+                    .get_index(coords)
+                    .is_ok_and(|idx| self.cells[idx] == Cell::Empty),
+                _ => false,
+            };
+            let stalled = self.n_empty == objective_left as u32
+                && self.temporary_walls.is_empty()
+                && self.active_cells.is_empty()
+                && self.sieges.is_empty();
+            if decided || stalled {
+                self.finish(Self::territory_outcome(n_red, n_blue));
+            }
+        }
+    }
+
+    /// Whether cells of the given color form a path between the left and
+    /// right or the top and bottom edge.
+    pub fn connects_edges(&self, color: Cell) -> bool {
+        let (w, h) = (self.width, self.height);
+        let left = (0..h).map(|y| (0, y));
+        let top = (0..w).map(|x| (x, 0));
+        let reaches = |starts: Vec<Coords>, done: &dyn Fn(Coords) -> bool| {
+            let mut visited = vec![false; self.cells.len()];
+            let mut stack = vec![];
+            for coords in starts {
+                let idx = self.get_index(coords).unwrap();
+                if self.cells[idx] == color {
+                    visited[idx] = true;
+                    stack.push(idx);
                 }
             }
+            while let Some(idx) = stack.pop() {
+                if done(self.get_coords(idx)) {
+                    return true;
+                }
+                for neighbour_idx in self.get_neighbours(self.get_coords(idx)) {
+                    if !visited[neighbour_idx] && self.cells[neighbour_idx] == color {
+                        visited[neighbour_idx] = true;
+                        stack.push(neighbour_idx);
+                    }
+                }
+            }
+            false
+        };
+        reaches(left.collect(), &|c| c.0 == w - 1) || reaches(top.collect(), &|c| c.1 == h - 1)
+    }
+
+    fn territory_outcome(red: u32, blue: u32) -> GameOutcome {
+        if red > blue {
+            GameOutcome::Territory(Color::Red)
+        } else if blue > red {
+            GameOutcome::Territory(Color::Blue)
+        } else {
+            GameOutcome::Draw
         }
-        self.active_cells = next_cells;
-        if self.n_empty == 0 {
+    }
+
+    /// Splits the empty cells into connected regions and records which colors
+    /// can spread into each of them. The spread follows one-way walls and
+    /// portals the same way `evolve` does, so cells past a wall that only
+    /// opens towards one color belong to that color.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions = vec![];
+        self.visit_regions(|cells, owner| {
+            regions.push(Region {
+                cells: cells.to_vec(),
+                owner,
+            })
+        });
+        regions
+    }
+
+    /// Calls `f` with the cells and owner of every empty region, reusing one
+    /// buffer for all of them. A region is a connected set of empty cells
+    /// the same colors can reach.
+    fn visit_regions(&self, mut f: impl FnMut(&[usize], RegionOwner)) {
+        let (red, blue) = (self.reachable(Cell::Red), self.reachable(Cell::Blue));
+        let owner = |idx: usize| match (red[idx], blue[idx]) {
+            (true, true) => RegionOwner::Contested,
+            (true, false) => RegionOwner::Red,
+            (false, true) => RegionOwner::Blue,
+            (false, false) => RegionOwner::Unreachable,
+        };
+        let mut visited = vec![false; self.cells.len()];
+        let mut stack = vec![];
+        let mut cells = vec![];
+        for start in 0..self.cells.len() {
+            if visited[start] || self.cells[start] != Cell::Empty {
+                continue;
+            }
+            let region_owner = owner(start);
+            visited[start] = true;
+            stack.push(start);
+            cells.clear();
+            while let Some(idx) = stack.pop() {
+                cells.push(idx);
+                let mut neighbours = self.get_neighbours(self.get_coords(idx));
+                if let Terrain::Portal(partner) = self.terrain[idx] {
+                    neighbours.push(partner);
+                }
+                for neighbour_idx in neighbours {
+                    if !visited[neighbour_idx]
+                        && self.cells[neighbour_idx] == Cell::Empty
+                        && owner(neighbour_idx) == region_owner
+                    {
+                        visited[neighbour_idx] = true;
+                        stack.push(neighbour_idx);
+                    }
+                }
+            }
+            f(&cells, region_owner);
+        }
+    }
+
+    /// Marks the empty cells the spread of `color` can still reach, through
+    /// one-way walls only along their arrow and through portals to their
+    /// partner.
+    fn reachable(&self, color: Cell) -> Vec<bool> {
+        let mut reached = vec![false; self.cells.len()];
+        let mut stack: Vec<usize> = (0..self.cells.len())
+            .filter(|&idx| self.cells[idx] == color)
+            .collect();
+        while let Some(idx) = stack.pop() {
+            for neighbour_idx in self.spread_neighbours(idx) {
+                if reached[neighbour_idx] || self.cells[neighbour_idx] != Cell::Empty {
+                    continue;
+                }
+                reached[neighbour_idx] = true;
+                stack.push(neighbour_idx);
+                if let Terrain::Portal(partner) = self.terrain[neighbour_idx] {
+                    if !reached[partner] && self.cells[partner] == Cell::Empty {
+                        reached[partner] = true;
+                        stack.push(partner);
+                    }
+                }
+            }
+        }
+        reached
+    }
+
+    /// Returns the group index of every cell, `usize::MAX` for cells that
+    /// are neither red nor blue, and the groups themselves.
+    fn groups(&self) -> (Vec<usize>, Vec<Group>) {
+        let mut group_of = vec![usize::MAX; self.cells.len()];
+        let mut groups = vec![];
+        for start in 0..self.cells.len() {
+            let color = self.cells[start];
+            if group_of[start] != usize::MAX || (color != Cell::Red && color != Cell::Blue) {
+                continue;
+            }
+            group_of[start] = groups.len();
+            let mut stack = vec![start];
+            let mut cells = vec![];
+            let mut free = false;
+            while let Some(idx) = stack.pop() {
+                cells.push(idx);
+                for neighbour_idx in self.get_neighbours(self.get_coords(idx)) {
+                    let neighbour = self.cells[neighbour_idx];
+                    if neighbour == Cell::Empty {
+                        free = true;
+                    } else if neighbour == color && group_of[neighbour_idx] == usize::MAX {
+                        group_of[neighbour_idx] = groups.len();
+                        stack.push(neighbour_idx);
+                    }
+                }
+            }
+            groups.push(Group { cells, color, free });
+        }
+        (group_of, groups)
+    }
+
+    /// Flips groups without empty neighbours that touch a free enemy group.
+    /// The smallest one goes first, as flipping merges it into the
+    /// encloser and frees it, until no such group is left. Pockets where
+    /// neither side is free stay as they are.
+    fn capture(&mut self) {
+        loop {
+            let (group_of, groups) = self.groups();
+            let captured = groups
+                .iter()
+                .filter(|group| !group.free)
+                .filter(|group| {
+                    group.cells.iter().any(|&idx| {
+                        self.get_neighbours(self.get_coords(idx))
+                            .into_iter()
+                            .any(|n| {
+                                group_of[n] != usize::MAX
+                                    && groups[group_of[n]].color != group.color
+                                    && groups[group_of[n]].free
+                            })
+                    })
+                })
+                .min_by_key(|group| group.cells.len());
+            let group = match captured {
+                Some(group) => group,
+                None => return,
+            };
+            let size = group.cells.len() as u32;
+            let encloser = if group.color == Cell::Red {
+                self.n_red -= size;
+                self.n_blue += size;
+                Cell::Blue
+            } else {
+                self.n_blue -= size;
+                self.n_red += size;
+                Cell::Red
+            };
+            for &idx in group.cells.iter() {
+                self.cells[idx] = encloser;
+                self.ages[idx] = 0;
+            }
+            let flipped: Vec<Coords> = group.cells.iter().map(|&i| self.get_coords(i)).collect();
+            self.active_cells.retain(|c| !flipped.contains(&c.1));
+            self.frozen.retain(|c| !flipped.contains(&c.1));
+        }
+    }
+
+    /// Ages every claimed cell by a tick. Cells past their lifespan fall
+    /// back to empty unless enough neighbours of their color hold them,
+    /// and the cells still young enough to spread form the new frontier.
+    fn decay(&mut self, decay: &DecaySettings) {
+        let mut expired = vec![];
+        for idx in 0..self.cells.len() {
+            let cell = self.cells[idx];
+            if cell != Cell::Red && cell != Cell::Blue {
+                continue;
+            }
+            self.ages[idx] += 1;
+            if self.ages[idx] >= decay.lifespan {
+                let support = self
+                    .get_neighbours(self.get_coords(idx))
+                    .into_iter()
+                    .filter(|&n| self.cells[n] == cell)
+                    .count() as u32;
+                if support < decay.support {
+                    expired.push(idx);
+                }
+            }
+        }
+        for idx in expired {
+            if self.cells[idx] == Cell::Red {
+                self.n_red -= 1;
+            } else {
+                self.n_blue -= 1;
+            }
+            self.cells[idx] = Cell::Empty;
+            self.ages[idx] = 0;
+            self.n_empty += 1;
+        }
+        self.active_cells = (0..self.cells.len())
+            .filter(|&idx| {
+                (self.cells[idx] == Cell::Red || self.cells[idx] == Cell::Blue)
+                    && self.ages[idx] <= decay.spread_age
+            })
+            .map(|idx| (self.cells[idx], self.get_coords(idx)))
+            .collect();
+    }
+
+    /// Territory each color is guaranteed by spreading alone: its claimed
+    /// cells plus the empty regions only it borders. Returns
+    /// `(red, blue, contestable)`, where `contestable` counts all claimed
+    /// cells and empty cells some color can still reach.
+    pub fn territory(&self) -> (u32, u32, u32) {
+        let (mut red, mut blue) = (self.n_red, self.n_blue);
+        let mut contestable = self.n_red + self.n_blue;
+        self.visit_regions(|cells, owner| {
+            let size = cells.len() as u32;
+            match owner {
+                RegionOwner::Red => red += size,
+                RegionOwner::Blue => blue += size,
+                RegionOwner::Contested => {}
+                RegionOwner::Unreachable => return,
+            }
+            contestable += size;
+        });
+        (red, blue, contestable)
+    }
+
+    /// Ends the game with the given outcome. A game that is already finished
+    /// keeps its first outcome.
+    pub fn finish(&mut self, outcome: GameOutcome) {
+        if !self.finished {
             self.finished = true;
+            self.outcome = Some(outcome);
         }
     }
 
+    /// Offers a draw for `color`, or accepts the opponent's standing offer.
+    /// An offer stands until the end of the opponent's next turn. Returns
+    /// whether anything changed.
+    pub fn offer_draw(&mut self, color: &Color) -> bool {
+        if self.finished || *color == Color::None || self.draw_offer == *color {
+            return false;
+        }
+        if self.draw_offer == color.opponent() {
+            self.finish(GameOutcome::Draw);
+        } else {
+            self.draw_offer = color.clone();
+        }
+        true
+    }
+
     pub fn is_finished(&self) -> bool {
         self.finished
     }
 
+    pub fn get_outcome(&self) -> Option<GameOutcome> {
+        self.outcome.clone()
+    }
+
     pub fn get_index(&self, coords: Coords) -> Result<usize, ()> {
-        let out = coords.0 + coords.1 * self.width;
-        if out > self.cells.len() {
+        if coords.0 >= self.width || coords.1 >= self.height {
             Err(())
         } else {
-            Ok(out)
+            Ok(coords.0 + coords.1 * self.width)
         }
     }
 
-    fn get_coords(&self, idx: usize) -> (usize, usize) {
-        ((idx % self.width), (idx / self.height))
+    pub fn get_coords(&self, idx: usize) -> (usize, usize) {
+        ((idx % self.width), (idx / self.width))
     }
 
-    fn get_neighbours(&self, coords: Coords) -> Vec<usize> {
-        let coords = (coords.0 as i32, coords.1 as i32);
-        let directions = [(0, 1), (1, 0), (-1, 0), (0, -1)];
-        directions
-            .map(|d| (coords.0 + d.0, coords.1 + d.1))
-            .into_iter()
-            .filter(|v| {
-                v.0 >= 0 && v.1 >= 0 && v.0 < WIDTH_UNIVERSE as i32 && v.1 < HEIGHT_UNIVERSE as i32
-            })
-            .map(|v| self.get_index((v.0 as usize, v.1 as usize)).unwrap())
-            .collect()
+    fn get_neighbours(&self, coords: Coords) -> Neighbours {
+        let mut neighbours = Neighbours::new();
+        if coords.1 + 1 < self.height {
+            neighbours.push(coords.0 + (coords.1 + 1) * self.width);
+        }
+        if coords.0 + 1 < self.width {
+            neighbours.push(coords.0 + 1 + coords.1 * self.width);
+        }
+        if coords.0 > 0 {
+            neighbours.push(coords.0 - 1 + coords.1 * self.width);
+        }
+        if coords.1 > 0 {
+            neighbours.push(coords.0 + (coords.1 - 1) * self.width);
+        }
+        if self.settings.neighbourhood == Neighbourhood::Moore {
+            let (x, y) = (coords.0 as i64, coords.1 as i64);
+            for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && ny >= 0 && nx < self.width as i64 && ny < self.height as i64 {
+                    neighbours.push(nx as usize + ny as usize * self.width);
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// Neighbours the spread can move into from `idx`, honoring one-way walls.
+    fn spread_neighbours(&self, idx: usize) -> Neighbours {
+        let from = self.get_coords(idx);
+        let mut neighbours = Neighbours::new();
+        for n in self.get_neighbours(from) {
+            let allowed = match self.terrain[n] {
+                Terrain::OneWay(direction) => {
+                    let to = self.get_coords(n);
+                    let step = (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32);
+                    step == direction.offset()
+                }
+                _ => true,
+            };
+            if allowed {
+                neighbours.push(n);
+            }
+        }
+        neighbours
+    }
+
+    /// Lets the spread claim an empty cell. A fortress is only besieged on
+    /// first contact and falls on the next tick, a portal passes the claim on
+    /// to its partner. Returns whether the cell was claimed right away.
+    fn spread_to(&mut self, next_cells: &mut Vec<(Cell, Coords)>, cell: Cell, idx: usize) -> bool {
+        let coords = self.get_coords(idx);
+        if self.cells[idx] != Cell::Empty {
+            return false;
+        }
+        match self.terrain[idx] {
+            Terrain::Fortress => {
+                if !self.sieges.iter().any(|s| s.1 == coords) {
+                    self.sieges.push((cell, coords));
+                }
+                false
+            }
+            Terrain::Portal(partner) => {
+                let _ = self._set_cell(CellWrapper::Extern(next_cells), &cell, coords);
+                let _ = self._set_cell(
+                    CellWrapper::Extern(next_cells),
+                    &cell,
+                    self.get_coords(partner),
+                );
+                true
+            }
+            _ => self
+                ._set_cell(CellWrapper::Extern(next_cells), &cell, coords)
+                .unwrap_or(false),
+        }
     }
 
     fn _set_cell(
@@ -211,8 +1492,12 @@ impl Universe {
             Cell::Red | Cell::Blue | Cell::Neutral => {
                 if self.cells[idx] == Cell::Empty {
                     self.cells[idx] = *cell;
+                    self.ages[idx] = 0;
                     cell_vec.push((*cell, coords));
                     self.n_empty -= 1;
+                    if *cell == Cell::Neutral {
+                        self.n_neutral += 1
+                    }
                     if *cell == Cell::Red {
                         self.n_red += 1
                     }
@@ -231,16 +1516,49 @@ impl Universe {
         self.cells.clone()
     }
 
+    pub fn get_ages(&self) -> &[u32] {
+        &self.ages
+    }
+
+    pub fn get_terrain(&self) -> Vec<Terrain> {
+        self.terrain.clone()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     pub fn get_cell_numbers(&self) -> (u32, u32, u32, u32) {
         (self.n_empty, self.n_red, self.n_blue, self.n_neutral)
     }
 
-    pub fn get_timer(&self) -> f64 {
-        self.timer
+    /// Charges `color` has left, none for spectators.
+    pub fn get_abilities(&self, color: &Color) -> &Abilities {
+        match color {
+            Color::Red => &self.red_abilities,
+            Color::Blue => &self.blue_abilities,
+            Color::None => &NO_ABILITIES,
+        }
+    }
+
+    pub fn get_tick(&self) -> u32 {
+        self.tick
     }
 
-    pub fn set_timer(&mut self, t: f64) {
-        self.timer = t;
+    pub fn get_clock(&self) -> &Clock {
+        &self.clock
+    }
+}
+
+fn cell_of(color: &Color) -> Cell {
+    match color {
+        Color::Red => Cell::Red,
+        Color::Blue => Cell::Blue,
+        Color::None => Cell::Empty,
     }
 }
 
@@ -255,3 +1573,509 @@ impl Point {
         Self { x, y }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapError;
+
+    fn cell_at(uni: &Universe, coords: Coords) -> Cell {
+        uni.get_cells()[uni.get_index(coords).unwrap()]
+    }
+
+    #[test]
+    fn spread_claims_neighbours() {
+        let mut uni = Universe::from_map("...\n...\n...").unwrap();
+        uni.set_cell(&Cell::Red, (1, 1)).unwrap();
+        uni.evolve();
+        assert_eq!(uni.get_cell_numbers(), (4, 5, 0, 0));
+        assert_eq!(cell_at(&uni, (0, 0)), Cell::Empty);
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Red);
+    }
+
+    #[test]
+    fn fortress_takes_two_ticks() {
+        let mut uni = Universe::from_map("..F..").unwrap();
+        uni.set_cell(&Cell::Red, (1, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Empty);
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Red);
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (3, 0)), Cell::Red);
+    }
+
+    #[test]
+    fn portal_passes_claims_on() {
+        let mut uni = Universe::from_map("..1#1..").unwrap();
+        uni.set_cell(&Cell::Blue, (1, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Blue);
+        assert_eq!(cell_at(&uni, (4, 0)), Cell::Blue);
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (5, 0)), Cell::Blue);
+
+        let mut uni = Universe::from_map("1#1").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Red);
+    }
+
+    #[test]
+    fn fertile_spreads_two_rings() {
+        let mut uni = Universe::from_map("*....").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Red);
+        assert_eq!(cell_at(&uni, (3, 0)), Cell::Empty);
+    }
+
+    #[test]
+    fn one_way_wall_blocks_the_other_direction() {
+        let mut uni = Universe::from_map(".>.").unwrap();
+        uni.set_cell(&Cell::Red, (2, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Empty);
+
+        let mut uni = Universe::from_map(".>.").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Red);
+    }
+
+    #[test]
+    fn wall_blocks_spread_until_it_expires() {
+        let mut uni = Universe::from_map(".....").unwrap();
+        assert!(uni.act(&Color::Red, &Action::Wall((2, 0))).unwrap());
+        assert_eq!(uni.get_abilities(&Color::Red).wall, 1);
+        uni.advance_clock(2.);
+        assert!(uni.act(&Color::Blue, &Action::Pick((0, 0))).unwrap());
+        for _ in 2..WALL_LIFETIME {
+            uni.advance_clock(2.);
+        }
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Neutral);
+        uni.advance_clock(2.);
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Blue);
+    }
+
+    #[test]
+    fn frozen_cell_skips_one_tick() {
+        let mut uni = Universe::from_map(".....").unwrap();
+        uni.act(&Color::Red, &Action::Pick((0, 0))).unwrap();
+        uni.advance_clock(2.);
+        assert!(uni.act(&Color::Blue, &Action::Freeze((1, 0))).unwrap());
+        uni.advance_clock(2.);
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Empty);
+        uni.advance_clock(2.);
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Red);
+    }
+
+    #[test]
+    fn abilities_run_out() {
+        let mut uni = Universe::from_map("#...............").unwrap();
+        uni.set_cell(&Cell::Blue, (15, 0)).unwrap();
+        assert!(uni
+            .act(&Color::Red, &Action::DoublePick((1, 0), (5, 0)))
+            .unwrap());
+        assert_eq!(uni.get_cell_numbers(), (12, 2, 1, 1));
+        uni.advance_clock(2.);
+        assert!(uni.act(&Color::Blue, &Action::Clear((0, 0))).unwrap());
+        uni.advance_clock(2.);
+        assert!(!uni
+            .act(&Color::Red, &Action::DoublePick((3, 0), (4, 0)))
+            .unwrap());
+        assert_eq!(cell_at(&uni, (0, 0)), Cell::Red);
+        assert_eq!(uni.get_abilities(&Color::None).left(Ability::Wall), 0);
+    }
+
+    #[test]
+    fn double_pick_needs_two_free_cells() {
+        let mut uni = Universe::from_map("#.....")
+            .unwrap()
+            .with_settings(GameSettings {
+                victory_rule: VictoryRule::Objective((5, 0)),
+                ..GameSettings::default()
+            });
+        for action in [
+            Action::DoublePick((1, 0), (5, 0)),
+            Action::DoublePick((1, 0), (0, 0)),
+            Action::DoublePick((1, 0), (9, 0)),
+        ] {
+            assert!(!uni.act(&Color::Red, &action).unwrap());
+        }
+        assert_eq!(uni.get_cell_numbers(), (5, 0, 0, 1));
+        assert_eq!(uni.get_abilities(&Color::Red).left(Ability::DoublePick), 1);
+    }
+
+    #[test]
+    fn enclosed_group_is_captured() {
+        let mut uni = Universe::from_map("#.....")
+            .unwrap()
+            .with_settings(GameSettings {
+                capture: true,
+                ..GameSettings::default()
+            });
+        uni.set_cell(&Cell::Red, (1, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (2, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Blue);
+        assert_eq!(uni.get_cell_numbers(), (2, 0, 3, 1));
+    }
+
+    #[test]
+    fn sealed_pocket_is_not_captured() {
+        let mut uni = Universe::from_map("..#..")
+            .unwrap()
+            .with_settings(GameSettings {
+                capture: true,
+                ..GameSettings::default()
+            });
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (1, 0)).unwrap();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (0, 0)), Cell::Red);
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Blue);
+    }
+
+    #[test]
+    fn unsupported_cells_decay() {
+        let mut uni = Universe::from_map(".....")
+            .unwrap()
+            .with_settings(GameSettings {
+                decay: Some(DecaySettings {
+                    spread_age: 1,
+                    lifespan: 3,
+                    support: 2,
+                }),
+                victory_rule: VictoryRule::ScoreAfter(100),
+                ..GameSettings::default()
+            });
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.evolve();
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (2, 0)), Cell::Red);
+        uni.evolve();
+        assert_eq!(cell_at(&uni, (0, 0)), Cell::Empty);
+        assert_eq!(cell_at(&uni, (1, 0)), Cell::Red);
+        assert_eq!(uni.get_cell_numbers(), (2, 3, 0, 0));
+    }
+
+    #[test]
+    fn moore_neighbourhood_spreads_diagonally() {
+        let mut uni = Universe::from_map("...\n...\n...")
+            .unwrap()
+            .with_settings(GameSettings {
+                neighbourhood: Neighbourhood::Moore,
+                ..GameSettings::default()
+            });
+        uni.set_cell(&Cell::Red, (1, 1)).unwrap();
+        uni.evolve();
+        assert_eq!(uni.get_cell_numbers(), (0, 9, 0, 0));
+    }
+
+    #[test]
+    fn invalid_maps_are_rejected() {
+        assert_eq!(Universe::from_map("").unwrap_err(), MapError::Empty);
+        assert_eq!(
+            Universe::from_map("...\n..").unwrap_err(),
+            MapError::Ragged(1)
+        );
+        assert_eq!(
+            Universe::from_map(".x.").unwrap_err(),
+            MapError::UnknownTile('x')
+        );
+        assert_eq!(
+            Universe::from_map("1.1.1").unwrap_err(),
+            MapError::UnpairedPortal(1)
+        );
+    }
+
+    #[test]
+    fn finish_keeps_the_first_outcome() {
+        let mut uni = Universe::from_map(".....").unwrap();
+        uni.finish(GameOutcome::Forfeit(Color::Blue));
+        uni.finish(GameOutcome::Draw);
+        assert!(uni.is_finished());
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Forfeit(Color::Blue)));
+        assert_eq!(
+            uni.get_outcome().unwrap().to_string(),
+            "Blue wins by forfeit"
+        );
+    }
+
+    #[test]
+    fn draw_offers_stand_for_one_turn() {
+        let mut uni = Universe::from_map(".....").unwrap();
+        assert!(!uni.offer_draw(&Color::None));
+        assert!(uni.offer_draw(&Color::Red));
+        assert!(!uni.offer_draw(&Color::Red));
+        // red's turn ends, blue may still take it
+        uni.advance_clock(2.);
+        assert_eq!(uni.draw_offer, Color::Red);
+        // blue lets its turn pass
+        uni.advance_clock(2.);
+        assert_eq!(uni.draw_offer, Color::None);
+        assert!(uni.offer_draw(&Color::Red));
+        assert!(uni.offer_draw(&Color::Blue));
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Draw));
+        assert!(!uni.offer_draw(&Color::Red));
+    }
+
+    #[test]
+    fn regions_follow_one_way_walls() {
+        let mut uni = Universe::from_map("....<.....").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (9, 0)).unwrap();
+        // red cannot pass the wall, blue can
+        assert_eq!(uni.territory(), (1, 6, 10));
+        let owners: Vec<(usize, RegionOwner)> = uni
+            .regions()
+            .iter()
+            .map(|r| (r.cells.len(), r.owner))
+            .collect();
+        assert!(owners.contains(&(3, RegionOwner::Contested)));
+        assert!(owners.contains(&(5, RegionOwner::Blue)));
+        // red can still pick behind the wall
+        uni.evolve();
+        assert!(!uni.is_finished());
+    }
+
+    #[test]
+    fn pickable_pockets_keep_the_game_going() {
+        let mut uni = Universe::from_map("..#..").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (1, 0)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        assert!(uni.pick(&Color::Red, (3, 0)).unwrap());
+        uni.advance_clock(2.);
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Territory(Color::Red)));
+    }
+
+    #[test]
+    fn expiring_walls_keep_the_game_going() {
+        let mut uni = Universe::from_map("...").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (2, 0)).unwrap();
+        assert!(uni.act(&Color::Red, &Action::Wall((1, 0))).unwrap());
+        for _ in 1..WALL_LIFETIME {
+            uni.evolve();
+            assert!(!uni.is_finished());
+        }
+        uni.evolve();
+        assert_eq!(uni.get_cell_numbers().0, 0);
+        assert!(matches!(uni.get_outcome(), Some(GameOutcome::Territory(_))));
+    }
+
+    #[test]
+    fn regions_follow_portals() {
+        let mut uni = Universe::from_map(".1#1...").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        assert_eq!(uni.territory(), (6, 0, 6));
+        assert!(uni.regions().iter().all(|r| r.owner == RegionOwner::Red));
+        // with the portal walled off the far side is out of reach
+        let mut uni = Universe::from_map(".1#1...").unwrap();
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Neutral, (1, 0)).unwrap();
+        assert_eq!(uni.territory(), (1, 0, 1));
+    }
+
+    fn ruled(map: &str, victory_rule: VictoryRule) -> Universe {
+        Universe::from_map(map)
+            .unwrap()
+            .with_settings(GameSettings {
+                victory_rule,
+                ..GameSettings::default()
+            })
+    }
+
+    #[test]
+    fn victory_rules_are_parsed_and_checked() {
+        let parse = |s: &str| s.parse::<VictoryRule>();
+        assert_eq!(parse("territory"), Ok(VictoryRule::TerritoryMajority));
+        assert_eq!(parse("share:60"), Ok(VictoryRule::BoardShare(60)));
+        assert!(parse("share:0").is_err());
+        assert!(parse("share:101").is_err());
+        assert_eq!(parse("edges"), Ok(VictoryRule::ConnectEdges));
+        assert_eq!(parse("objective:3,4"), Ok(VictoryRule::Objective((3, 4))));
+        assert!(parse("objective:3").is_err());
+        assert_eq!(parse("score:200"), Ok(VictoryRule::ScoreAfter(200)));
+        assert!(parse("conquest").is_err());
+        let settings = GameSettings {
+            victory_rule: VictoryRule::Objective((10, 4)),
+            ..GameSettings::default()
+        };
+        assert!(settings.check(10, 10).is_err());
+        assert!(settings.check(11, 5).is_ok());
+        let decay = GameSettings {
+            decay: Some(DecaySettings::default()),
+            ..GameSettings::default()
+        };
+        assert!(decay.check(10, 10).is_err());
+        let scored = GameSettings {
+            victory_rule: VictoryRule::ScoreAfter(200),
+            ..decay
+        };
+        assert!(scored.check(10, 10).is_ok());
+        for (lifespan, support) in [(0, 2), (16, 5)] {
+            let bad = GameSettings {
+                decay: Some(DecaySettings {
+                    spread_age: 4,
+                    lifespan,
+                    support,
+                }),
+                ..scored.clone()
+            };
+            assert!(bad.check(10, 10).is_err());
+        }
+        let moore = GameSettings {
+            decay: Some(DecaySettings {
+                spread_age: 4,
+                lifespan: 16,
+                support: 5,
+            }),
+            neighbourhood: Neighbourhood::Moore,
+            ..scored
+        };
+        assert!(moore.check(10, 10).is_ok());
+    }
+
+    #[test]
+    fn territory_majority_ends_on_a_full_board() {
+        let mut uni = ruled(".......", VictoryRule::TerritoryMajority);
+        uni.set_cell(&Cell::Red, (1, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (6, 0)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        uni.evolve();
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Territory(Color::Red)));
+    }
+
+    #[test]
+    fn board_share_ends_at_the_share() {
+        let mut uni = ruled("..........", VictoryRule::BoardShare(30));
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        uni.evolve();
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Territory(Color::Red)));
+    }
+
+    #[test]
+    fn connect_edges_ends_once_connected() {
+        let mut uni = ruled("...\n...\n...\n...\n...", VictoryRule::ConnectEdges);
+        uni.set_cell(&Cell::Red, (0, 2)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        uni.evolve();
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Connected(Color::Red)));
+    }
+
+    #[test]
+    fn objective_stays_empty_until_spread_reaches_it() {
+        let mut uni = ruled("..#..", VictoryRule::Objective((2, 0)));
+        // the wall on the objective is gone
+        assert_eq!(uni.get_cell_numbers(), (5, 0, 0, 0));
+        assert!(!uni.set_cell(&Cell::Red, (2, 0)).unwrap());
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        uni.evolve();
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Objective(Color::Red)));
+    }
+
+    #[test]
+    fn score_after_ends_at_the_tick() {
+        let mut uni = ruled("........", VictoryRule::ScoreAfter(2));
+        uni.set_cell(&Cell::Red, (0, 0)).unwrap();
+        uni.set_cell(&Cell::Red, (1, 0)).unwrap();
+        uni.set_cell(&Cell::Blue, (7, 0)).unwrap();
+        uni.evolve();
+        assert!(!uni.is_finished());
+        uni.evolve();
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Territory(Color::Red)));
+    }
+
+    fn clocked(mode: ClockMode, turn_length: f64, pick_ends_turn: bool) -> Universe {
+        Universe::from_map(".....")
+            .unwrap()
+            .with_settings(GameSettings {
+                clock: ClockSettings {
+                    mode,
+                    turn_length,
+                    pick_ends_turn,
+                },
+                ..GameSettings::default()
+            })
+    }
+
+    #[test]
+    fn bank_runs_down_and_gains_the_increment() {
+        let bank = ClockMode::Bank {
+            initial: 10.,
+            increment: 2.,
+        };
+        let mut uni = clocked(bank, 2., false);
+        assert!(!uni.advance_clock(1.5));
+        assert_eq!(uni.get_clock().bank(&Color::Red), 8.5);
+        // a pick hands the turn over and earns the increment
+        assert!(uni.pick(&Color::Red, (0, 0)).unwrap());
+        assert_eq!(uni.get_clock().turn, Color::Blue);
+        assert_eq!(uni.get_clock().bank(&Color::Red), 10.5);
+        // the turn length still ends a turn, without an increment
+        assert!(uni.advance_clock(2.));
+        assert_eq!(uni.get_clock().turn, Color::Red);
+        assert_eq!(uni.get_clock().bank(&Color::Blue), 8.);
+    }
+
+    #[test]
+    fn banks_are_checked() {
+        let check = |initial, increment| {
+            let settings = GameSettings {
+                clock: ClockSettings {
+                    mode: ClockMode::Bank { initial, increment },
+                    ..ClockSettings::default()
+                },
+                ..GameSettings::default()
+            };
+            settings.check(10, 10)
+        };
+        assert!(check(60., 0.).is_ok());
+        assert!(check(60., 2.).is_ok());
+        assert!(check(-1., 0.).is_err());
+        assert!(check(0., 0.).is_err());
+        assert!(check(f64::INFINITY, 0.).is_err());
+        assert!(check(f64::NAN, 0.).is_err());
+        assert!(check(60., -1.).is_err());
+        assert!(check(60., f64::NAN).is_err());
+    }
+
+    #[test]
+    fn empty_bank_forfeits() {
+        let bank = ClockMode::Bank {
+            initial: 3.,
+            increment: 0.,
+        };
+        let mut uni = clocked(bank, 10., false);
+        uni.advance_clock(2.);
+        assert!(!uni.is_finished());
+        uni.advance_clock(1.5);
+        assert_eq!(uni.get_outcome(), Some(GameOutcome::Forfeit(Color::Blue)));
+        assert_eq!(uni.get_clock().bank(&Color::Red), 0.);
+        assert!(!uni.advance_clock(10.));
+    }
+
+    #[test]
+    fn pick_ends_turn_only_when_set() {
+        let mut uni = clocked(ClockMode::Fixed, 2., false);
+        assert!(uni.pick(&Color::Red, (0, 0)).unwrap());
+        assert!(!uni.pick(&Color::Red, (4, 0)).unwrap());
+        assert_eq!(uni.get_clock().turn, Color::Red);
+        assert!(uni.advance_clock(2.));
+        assert_eq!(uni.get_clock().turn, Color::Blue);
+
+        let mut uni = clocked(ClockMode::Fixed, 2., true);
+        assert!(uni.pick(&Color::Red, (0, 0)).unwrap());
+        assert_eq!(uni.get_clock().turn, Color::Blue);
+        assert_eq!(uni.get_tick(), 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! The lobby as pushed to its visitors. `GET /api/lobby/feed` is an event
+//! stream that sends a fresh `Lobby` as JSON whenever something in it
+//! changes. Challenges go through `POST /api/lobby/challenge`,
+//! `/api/lobby/accept` and `/api/lobby/decline`; once one is accepted the
+//! challenger takes the seat waiting for them with `POST /api/user/join/{id}`,
+//! which also joins open rooms.
+//!
+//! Private games are created with `POST /api/lobby/private` and never show
+//! up in the lobby. The creator shares the link to `/invite/{token}`, the
+//! second seat can only be taken with `POST /api/user/invite/{token}`.
+
+use crate::chat::ChatMessage;
+use crate::constants::*;
+use crate::GameSettings;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum UserStatus {
+    Idle,
+    /// seated and waiting for an opponent
+    InQueue,
+    InGame,
+    Spectating,
+}
+
+impl fmt::Display for UserStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UserStatus::Idle => write!(f, "idle"),
+            UserStatus::InQueue => write!(f, "waiting for a game"),
+            UserStatus::InGame => write!(f, "in a game"),
+            UserStatus::Spectating => write!(f, "watching a game"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyUser {
+    pub name: String,
+    pub status: UserStatus,
+}
+
+/// Universe with one player waiting for a second.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OpenRoom {
+    pub id: u32,
+    pub host: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunningGame {
+    pub id: u32,
+    pub red: String,
+    pub blue: String,
+    /// red and blue cells
+    pub score: (u32, u32),
+    pub spectators: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub from: String,
+    pub to: String,
+    /// the challenger allows hints, if the server does
+    pub hints: bool,
+    /// universe waiting for the challenger once the challenge is accepted
+    pub universe: Option<u32>,
+}
+
+/// Name of a user to challenge, or of the challenger to answer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChallengeTarget {
+    pub name: String,
+    /// whether a new challenge allows hints, ignored in answers
+    #[serde(default)]
+    pub hints: bool,
+}
+
+/// Board and rules the creator of a private game chose.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PrivateGame {
+    pub width: u32,
+    pub height: u32,
+    pub settings: GameSettings,
+}
+
+impl PrivateGame {
+    /// Refuses boards outside `MIN_UNIVERSE_SIZE` and `MAX_UNIVERSE_SIZE`,
+    /// turns longer than `MAX_TURN_LENGTH` seconds and settings
+    /// `GameSettings::check` refuses for the board.
+    pub fn check(&self) -> Result<(), String> {
+        let sizes = MIN_UNIVERSE_SIZE..=MAX_UNIVERSE_SIZE;
+        if !sizes.contains(&self.width) || !sizes.contains(&self.height) {
+            return Err(format!(
+                "the board has to be between {} and {} cells wide and high",
+                MIN_UNIVERSE_SIZE, MAX_UNIVERSE_SIZE
+            ));
+        }
+        if self.settings.clock.turn_length > MAX_TURN_LENGTH {
+            return Err(format!(
+                "turns have to last between 0 and {} seconds",
+                MAX_TURN_LENGTH
+            ));
+        }
+        self.settings
+            .check(self.width as usize, self.height as usize)
+    }
+}
+
+/// Answer to creating a private game, whose creator plays red.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrivateGameCreated {
+    /// part of the invite link
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Lobby {
+    pub users: Vec<LobbyUser>,
+    pub rooms: Vec<OpenRoom>,
+    pub games: Vec<RunningGame>,
+    pub challenges: Vec<Challenge>,
+    pub chat: Vec<ChatMessage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockMode, DecaySettings, VictoryRule};
+
+    #[test]
+    fn private_games_are_checked() {
+        let mut game = PrivateGame {
+            width: 20,
+            height: 10,
+            settings: GameSettings::default(),
+        };
+        assert!(game.check().is_ok());
+        game.settings.victory_rule = VictoryRule::Objective((15, 12));
+        assert!(game.check().is_err());
+        game.settings.victory_rule = VictoryRule::Objective((15, 8));
+        assert!(game.check().is_ok());
+        game.settings.clock.turn_length = 0.;
+        assert!(game.check().is_err());
+        game.settings.clock.turn_length = 2.;
+        for initial in [-1., 0., f64::INFINITY, f64::NAN] {
+            game.settings.clock.mode = ClockMode::Bank {
+                initial,
+                increment: 0.,
+            };
+            assert!(game.check().is_err());
+        }
+        game.settings.clock.mode = ClockMode::Bank {
+            initial: 60.,
+            increment: f64::NAN,
+        };
+        assert!(game.check().is_err());
+        game.settings.clock.mode = ClockMode::Bank {
+            initial: 60.,
+            increment: 2.,
+        };
+        assert!(game.check().is_ok());
+        game.settings.victory_rule = VictoryRule::ScoreAfter(100);
+        for (lifespan, support) in [(0, 2), (16, 5)] {
+            game.settings.decay = Some(DecaySettings {
+                spread_age: 4,
+                lifespan,
+                support,
+            });
+            assert!(game.check().is_err());
+        }
+        game.settings.decay = Some(DecaySettings::default());
+        assert!(game.check().is_ok());
+        game.width = MAX_UNIVERSE_SIZE + 1;
+        assert!(game.check().is_err());
+    }
+}
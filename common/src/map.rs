@@ -0,0 +1,78 @@
+//! Boards stored as plain text, one line per row:
+//!
+//! ```text
+//! .  empty          #  neutral wall      F  fortress
+//! *  fertile        0-9  portal, the two cells with the same digit are linked
+//! > < ^ v  one-way wall, spread can only enter it moving along the arrow
+//! ```
+
+use crate::{Cell, Direction, Terrain, Universe};
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum MapError {
+    Empty,
+    Ragged(usize),
+    UnknownTile(char),
+    UnpairedPortal(usize),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Empty => write!(f, "map has no rows"),
+            MapError::Ragged(row) => write!(f, "row {} has a different length", row),
+            MapError::UnknownTile(c) => write!(f, "unknown tile '{}'", c),
+            MapError::UnpairedPortal(d) => write!(f, "portal {} needs exactly two cells", d),
+        }
+    }
+}
+
+impl Universe {
+    pub fn from_map(map: &str) -> Result<Universe, MapError> {
+        let rows: Vec<&str> = map
+            .lines()
+            .map(str::trim_end)
+            .filter(|l| !l.is_empty())
+            .collect();
+        let width = rows.first().ok_or(MapError::Empty)?.chars().count();
+        let mut cells = vec![];
+        let mut terrain = vec![];
+        let mut portals: [Vec<usize>; 10] = Default::default();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(MapError::Ragged(y));
+            }
+            for tile in row.chars() {
+                let (cell, ground) = match tile {
+                    '.' => (Cell::Empty, Terrain::Plain),
+                    '#' => (Cell::Neutral, Terrain::Plain),
+                    'F' => (Cell::Empty, Terrain::Fortress),
+                    '*' => (Cell::Empty, Terrain::Fertile),
+                    '>' => (Cell::Empty, Terrain::OneWay(Direction::East)),
+                    '<' => (Cell::Empty, Terrain::OneWay(Direction::West)),
+                    '^' => (Cell::Empty, Terrain::OneWay(Direction::North)),
+                    'v' => (Cell::Empty, Terrain::OneWay(Direction::South)),
+                    '0'..='9' => {
+                        portals[tile.to_digit(10).unwrap() as usize].push(cells.len());
+                        (Cell::Empty, Terrain::Plain)
+                    }
+                    c => return Err(MapError::UnknownTile(c)),
+                };
+                cells.push(cell);
+                terrain.push(ground);
+            }
+        }
+        for (digit, ends) in portals.iter().enumerate() {
+            match ends[..] {
+                [] => {}
+                [a, b] => {
+                    terrain[a] = Terrain::Portal(b);
+                    terrain[b] = Terrain::Portal(a);
+                }
+                _ => return Err(MapError::UnpairedPortal(digit)),
+            }
+        }
+        Ok(Universe::from_parts(width, rows.len(), cells, terrain))
+    }
+}
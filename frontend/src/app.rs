@@ -1,10 +1,17 @@
+use common::bot::{now_ms, Bot, Difficulty};
+use common::chat::{ChatMessage, ChatPost};
 use common::constants::*;
+use common::lobby::{ChallengeTarget, Lobby, PrivateGame, PrivateGameCreated, UserStatus};
 use common::*;
 use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
 use serde_json;
-use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen::{closure::Closure, Clamped, JsCast, JsValue};
 use wasm_bindgen_futures;
-use web_sys::{console, CanvasRenderingContext2d, HtmlCanvasElement, HtmlInputElement};
+use web_sys::{
+    console, AddEventListenerOptions, CanvasRenderingContext2d, EventSource, HtmlCanvasElement,
+    HtmlInputElement, ImageData, MessageEvent, Storage,
+};
 use yew::prelude::*;
 use yew_router::{navigator, prelude::*};
 
@@ -17,6 +24,8 @@ macro_rules! log {
 enum UserMsg {
     UpdateInput(String),
     ButtonPressed(String),
+    /// the server refused the name
+    Rejected(String),
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -26,6 +35,7 @@ struct UserProps {
 
 struct UserNamePrompt {
     input_value: String,
+    error: Option<String>,
 }
 
 impl Component for UserNamePrompt {
@@ -35,6 +45,7 @@ impl Component for UserNamePrompt {
     fn create(_ctx: &Context<Self>) -> Self {
         UserNamePrompt {
             input_value: "".into(),
+            error: None,
         }
     }
 
@@ -44,6 +55,7 @@ impl Component for UserNamePrompt {
                 self.input_value = val;
             }
             UserMsg::ButtonPressed(val) => ctx.props().app_hook.emit(val.clone().into()),
+            UserMsg::Rejected(error) => self.error = Some(error),
         }
         true
     }
@@ -67,20 +79,29 @@ impl Component for UserNamePrompt {
             let input_value = self.input_value.clone();
             Callback::from(move |_| {
                 let input_value = input_value.clone();
-                link.send_message(UserMsg::ButtonPressed(input_value.clone()));
+                let link = link.clone();
                 let navigator = navigator.clone();
                 wasm_bindgen_futures::spawn_local(async move {
                     let new_user = User::new(input_value.clone());
-                    Request::post("/api/usernames/")
+                    let response = Request::post("/api/usernames/")
                         .header("Content-Type", "application/json")
                         .body(serde_json::to_string(&new_user).unwrap())
                         .send()
                         .await
                         .unwrap();
-                    navigator.push(&Route::ActiveUsers);
+                    if response.ok() {
+                        link.send_message(UserMsg::ButtonPressed(input_value));
+                        navigator.push(&Route::ActiveUsers);
+                    } else {
+                        link.send_message(UserMsg::Rejected(response.text().await.unwrap()));
+                    }
                 });
             })
         };
+        let error = match &self.error {
+            Some(e) => html! { <p role="alert">{e}</p> },
+            None => html! {},
+        };
         html!(<>
           <label for="cautious-input">
                 { "Enter user name:" }
@@ -90,13 +111,25 @@ impl Component for UserNamePrompt {
                     value={self.input_value.clone()}
                 />
             </label><button onclick={submit_button}>{"Submit"}</button>
+            {error}
+            <p>
+                {"Play offline: "}
+                <Link<Route> to={Route::LocalHotSeat}>{"two players on this device"}</Link<Route>>
+                { for Difficulty::ALL.iter().map(|difficulty| html! {<>
+                    {" | "}
+                    <Link<Route> to={Route::LocalBot { difficulty: difficulty.to_string() }}>
+                        {format!("against the {difficulty} computer")}
+                    </Link<Route>>
+                </>}) }
+            </p>
+            <p><Link<Route> to={Route::Settings}>{"Settings"}</Link<Route>></p>
         </>)
     }
 }
 
 #[derive(PartialEq, Properties)]
 struct VictoryProps {
-    winner: String,
+    outcome: String,
 }
 
 struct VictoryScreen;
@@ -115,13 +148,14 @@ impl Component for VictoryScreen {
         let return_button: Callback<MouseEvent> = {
             Callback::from(move |_| {
                 wasm_bindgen_futures::spawn_local(async move {
-                    Request::post("/api/universe/kill").send().await.unwrap();
+                    // offline games have nothing on the server to end
+                    let _ = Request::post("/api/universe/kill").send().await;
                 });
                 navigator.push(&Route::Home);
             })
         };
         html!(<>
-                <p>{"the winner is: "}{props.winner.clone()}</p>
+                <p>{props.outcome.clone()}</p>
                 <button onclick={return_button}>{"Back"}</button>
             </>)
     }
@@ -134,53 +168,234 @@ struct LobbyProperties {
 }
 
 enum LobbyMsg {
-    UpdateUsers(UserList),
+    Update(Lobby),
     Color(String),
+    Challenge(String),
+    /// allow hints in the games of new challenges
+    ChallengeHints(bool),
+    Accept(String),
+    Decline(String),
+    Join(u32),
+    Spectate(u32),
+    /// the server seated the user as a spectator
+    Watch,
 }
 
 struct ActiveUsers {
-    json_data: UserList,
+    lobby: Lobby,
+    /// stream the server pushes the lobby through
+    feed: Option<EventSource>,
+    /// called by the feed, so it has to live as long as the feed does
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    /// already taking the seat of an accepted challenge
+    joining: bool,
+    /// new challenges allow hints
+    hints: bool,
+}
+
+impl ActiveUsers {
+    /// Posts the name of a challenged or challenging user.
+    fn answer(&self, ctx: &Context<Self>, url: &'static str, name: String, seated: bool) {
+        let link = ctx.link().clone();
+        let hints = self.hints;
+        wasm_bindgen_futures::spawn_local(async move {
+            let response = Request::post(url)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&ChallengeTarget { name, hints }).unwrap())
+                .send()
+                .await
+                .unwrap();
+            if seated {
+                if let Ok(color) = response.json::<ColorSender>().await {
+                    link.send_message(LobbyMsg::Color(color.value));
+                }
+            }
+        });
+    }
 }
 
 impl Component for ActiveUsers {
     type Message = LobbyMsg;
     type Properties = LobbyProperties;
 
-    fn create(_ctx: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let lobby = event
+                .data()
+                .as_string()
+                .and_then(|data| serde_json::from_str(&data).ok());
+            if let Some(lobby) = lobby {
+                link.send_message(LobbyMsg::Update(lobby));
+            }
+        });
+        // the browser reconnects by itself when the stream breaks
+        let feed = EventSource::new("/api/lobby/feed").ok();
+        if let Some(source) = &feed {
+            source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        }
         Self {
-            json_data: UserList::new(),
+            lobby: Lobby::default(),
+            feed,
+            _on_message: on_message,
+            joining: false,
+            hints: true,
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            LobbyMsg::UpdateUsers(val) => self.json_data = val,
+            LobbyMsg::Update(lobby) => {
+                let me = &ctx.props().player_name;
+                let accepted = lobby
+                    .challenges
+                    .iter()
+                    .find(|c| c.from == *me)
+                    .and_then(|c| c.universe);
+                if let (Some(uidx), false) = (accepted, self.joining) {
+                    self.joining = true;
+                    ctx.link().send_message(LobbyMsg::Join(uidx));
+                }
+                self.lobby = lobby;
+            }
             LobbyMsg::Color(val) => {
                 let navigator = ctx.link().navigator().unwrap();
                 if val != "none" {
-                    navigator.push(&Route::InGame);
+                    ctx.props().app_hook.emit(val.into());
+                    navigator.push(&Route::PreGame);
                 }
             }
+            LobbyMsg::Challenge(name) => {
+                self.answer(ctx, "/api/lobby/challenge", name, false);
+                return false;
+            }
+            LobbyMsg::ChallengeHints(hints) => {
+                self.hints = hints;
+            }
+            LobbyMsg::Accept(name) => {
+                self.answer(ctx, "/api/lobby/accept", name, true);
+                return false;
+            }
+            LobbyMsg::Decline(name) => {
+                self.answer(ctx, "/api/lobby/decline", name, false);
+                return false;
+            }
+            LobbyMsg::Join(uidx) => {
+                let link = ctx.link().clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let url = format!("/api/user/join/{uidx}");
+                    let response = Request::post(&url).send().await.unwrap();
+                    if let Ok(color) = response.json::<ColorSender>().await {
+                        link.send_message(LobbyMsg::Color(color.value));
+                    }
+                });
+                return false;
+            }
+            LobbyMsg::Spectate(uidx) => {
+                let link = ctx.link().clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let url = format!("/api/universe/spectate/{uidx}");
+                    if Request::post(&url).send().await.unwrap().ok() {
+                        link.send_message(LobbyMsg::Watch);
+                    }
+                });
+                return false;
+            }
+            LobbyMsg::Watch => {
+                ctx.props().app_hook.emit("".into());
+                ctx.link().navigator().unwrap().push(&Route::InGame);
+            }
         }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        let link = ctx.link().clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            let url = "/api/usernames/total/";
-            let response = Request::get(url)
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            console::log_1(&JsValue::from(format!("{:?}", response)));
-            link.send_message(LobbyMsg::UpdateUsers(response));
-        });
-        let n_users = self.json_data.n_users;
-        let user_list = self.json_data.users.clone();
+        let me = ctx.props().player_name.clone();
+        let n_users = self.lobby.users.len();
+        let challenged = |name: &str| {
+            self.lobby
+                .challenges
+                .iter()
+                .any(|c| c.from == me && c.to == name)
+        };
+        let users = self
+            .lobby
+            .users
+            .iter()
+            .map(|user| {
+                let challenge = if user.name != me
+                    && user.status == UserStatus::Idle
+                    && !challenged(&user.name)
+                {
+                    let name = user.name.clone();
+                    html! {
+                        <button onclick={ctx.link().callback(move |_| LobbyMsg::Challenge(name.clone()))}>
+                            {"Challenge"}
+                        </button>
+                    }
+                } else {
+                    html! {}
+                };
+                html! { <li>{format!("{} ({}) ", user.name, user.status)}{challenge}</li> }
+            })
+            .collect::<Html>();
+        let challenges = self
+            .lobby
+            .challenges
+            .iter()
+            .filter(|c| c.universe.is_none())
+            .map(|c| {
+                if c.to == me {
+                    let (accept, decline) = (c.from.clone(), c.from.clone());
+                    let rules = if c.hints { "" } else { "without hints " };
+                    html! {
+                        <p>{format!("{} challenges you {}", c.from, rules)}
+                            <button onclick={ctx.link().callback(move |_| LobbyMsg::Accept(accept.clone()))}>{"Accept"}</button>
+                            <button onclick={ctx.link().callback(move |_| LobbyMsg::Decline(decline.clone()))}>{"Decline"}</button>
+                        </p>
+                    }
+                } else if c.from == me {
+                    let to = c.to.clone();
+                    html! {
+                        <p>{format!("Waiting for {} to answer ", c.to)}
+                            <button onclick={ctx.link().callback(move |_| LobbyMsg::Decline(to.clone()))}>{"Withdraw"}</button>
+                        </p>
+                    }
+                } else {
+                    html! {}
+                }
+            })
+            .collect::<Html>();
+        let rooms = self
+            .lobby
+            .rooms
+            .iter()
+            .filter(|room| room.host != me)
+            .map(|room| {
+                let id = room.id;
+                html! {
+                    <li>{format!("{} is waiting for an opponent ", room.host)}
+                        <button onclick={ctx.link().callback(move |_| LobbyMsg::Join(id))}>{"Join"}</button>
+                    </li>
+                }
+            })
+            .collect::<Html>();
+        let games = self
+            .lobby
+            .games
+            .iter()
+            .map(|game| {
+                let id = game.id;
+                html! {
+                    <li>{format!(
+                            "{} (red) {} : {} {} (blue), {} watching ",
+                            game.red, game.score.0, game.score.1, game.blue, game.spectators
+                        )}
+                        <button onclick={ctx.link().callback(move |_| LobbyMsg::Spectate(id))}>{"Watch"}</button>
+                    </li>
+                }
+            })
+            .collect::<Html>();
         let start_game: Callback<MouseEvent> = {
             let link = ctx.link().clone();
             Callback::from(move |_| {
@@ -198,17 +413,57 @@ impl Component for ActiveUsers {
                 });
             })
         };
+        let bot_buttons = Difficulty::ALL
+            .iter()
+            .map(|&difficulty| {
+                let link = ctx.link().clone();
+                let play_bot = Callback::from(move |_| {
+                    let link = link.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let url = format!("/api/user/bot/{difficulty}");
+                        let response: ColorSender = Request::get(&url)
+                            .send()
+                            .await
+                            .unwrap()
+                            .json()
+                            .await
+                            .unwrap();
+                        link.send_message(LobbyMsg::Color(response.value))
+                    });
+                });
+                html! { <button onclick={play_bot}>{format!("vs computer ({difficulty})")}</button> }
+            })
+            .collect::<Html>();
+        let onhints = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            LobbyMsg::ChallengeHints(input.checked())
+        });
         html!(
         <>
             <p>{"Number of users "}{n_users}</p>
-            <p>
-                { for user_list.iter().map(|item| html!{ <li>{ item.name.clone() }</li> }) }
-            </p>
+            <ul>{users}</ul>
+            <label>
+                <input type="checkbox" checked={self.hints} onchange={onhints}/>
+                {"Allow hints when challenging"}
+            </label>
+            {challenges}
+            <ul>{rooms}</ul>
+            <ul>{games}</ul>
             <p>{"your name: "}{ctx.props().player_name.clone()}</p>
             <button onclick={start_game}>{"start!!!"}</button>
+            {bot_buttons}
+            <p><Link<Route> to={Route::PrivateGame}>{"Create private game"}</Link<Route>></p>
+            <ChatBox messages={self.lobby.chat.clone()} url="/api/usernames/chat"/>
+            <p><Link<Route> to={Route::Settings}>{"Settings"}</Link<Route>></p>
         </>
         )
     }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(source) = self.feed.take() {
+            source.close();
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -230,16 +485,22 @@ impl Component for PreGame {
         Self
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, _msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PreGameMsg::CheckReady(ready) => {
+                if ready {
+                    ctx.link().navigator().unwrap().push(&Route::InGame);
+                }
+            }
+        }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let color_string = ctx.props().color.clone();
-        let navigator = ctx.link().navigator().unwrap();
+        let link = ctx.link().clone();
         wasm_bindgen_futures::spawn_local(async move {
             let url = "/api/user/pregame";
-            log!("sth");
             let response: PreGameData = Request::get(url)
                 .send()
                 .await
@@ -247,9 +508,7 @@ impl Component for PreGame {
                 .json()
                 .await
                 .unwrap();
-            if response.ready {
-                navigator.push(&Route::InGame)
-            }
+            link.send_message(PreGameMsg::CheckReady(response.ready));
         });
 
         html!(
@@ -264,17 +523,127 @@ impl Component for PreGame {
 pub struct InGame {
     canvas: NodeRef,
     universe: Universe,
+    selected: Option<Ability>,
+    first_pick: Option<Coords>,
+    show_hints: bool,
+    hints: Vec<f32>,
+    /// tick and cell numbers the hints were worked out for
+    hints_for: Option<(u32, (u32, u32, u32, u32))>,
+    show_projection: bool,
+    hover: Option<Coords>,
+    /// cells the hovered action would win on the next tick, `None` if it is
+    /// not allowed
+    preview: Option<Vec<usize>>,
+    /// the last tap came from a touch screen, taps only select a cell and a
+    /// second tap on it confirms
+    touch: bool,
+    armed: Option<Coords>,
+    display: DisplaySettings,
+    /// cells claimed since the last snapshot, with the side their claim came
+    /// from, `(0, 0)` for picks
+    animation: Vec<(usize, (i32, i32))>,
+    /// time of the first frame of the animation
+    animation_start: Option<f64>,
+    animation_progress: f64,
+    frame_requested: bool,
+    /// read out by screen readers whenever it changes
+    announcement: String,
+    /// what lies under the hovered cell, read out as the cursor moves
+    cursor_text: String,
+    /// what each cell looked like when it was last painted, `None` once the
+    /// canvas has to be painted from scratch
+    drawn: Vec<Option<CellLook>>,
+    /// canvas size in device pixels `drawn` belongs to, resizing the canvas
+    /// clears it
+    drawn_size: (u32, u32),
+    /// zoom and pan `drawn` belongs to
+    drawn_view: (f64, (f64, f64)),
+    /// zoom chosen by the player, `None` fits the whole board into the window
+    zoom: Option<f64>,
+    /// board pixel at the top left corner of the canvas
+    pan: (f64, f64),
+    /// pointers pressed on the canvas, by id, at their last position
+    pointers: Vec<(i32, (f64, f64))>,
+    /// how far the pressed pointers moved, past `DRAG_THRESHOLD` it is a
+    /// drag and not a click
+    drag_distance: f64,
+    minimap: NodeRef,
+    /// wheel events are listened to by hand, yew only listens passively and
+    /// the page would scroll along
+    wheel_listener: Option<Closure<dyn FnMut(WheelEvent)>>,
+    /// plays blue in games against the computer
+    bot: Option<Box<dyn Bot + Send>>,
+    /// time the clock of an offline game last ran, in milliseconds
+    last_tick: f64,
+    /// interval running the clock of an offline game or polling the
+    /// server, and its callback
+    timer: Option<(i32, Closure<dyn FnMut()>)>,
+    /// a poll is on its way, the next one waits for it
+    polling: bool,
+    chat: Vec<ChatMessage>,
+}
+
+/// Everything painted inside one cell.
+#[derive(Clone, PartialEq)]
+struct CellLook {
+    cell: Cell,
+    terrain: Terrain,
+    /// opacity of the decay fade, in percent
+    fade: u8,
+    projected: Color,
+    /// opacity of the hint tint, in percent
+    hint: u8,
+    objective: bool,
+    /// hovered, and whether the selected action is allowed there
+    hovered: Option<bool>,
+    /// won by the hovered action on the next tick
+    ghost: bool,
+    /// part of the cell already claimed while it is animated, in percent,
+    /// and the side it grows from
+    grow: (u8, (i32, i32)),
 }
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct InGameProps {
     app_hook: Callback<AttrValue>,
+    color: String,
+    /// plays the game in the browser instead of on the server
+    #[prop_or_default]
+    local: Option<LocalGame>,
+}
+
+/// Games played without the server.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LocalGame {
+    /// two players taking turns on one device
+    HotSeat,
+    /// red against the computer playing blue
+    Bot(Difficulty),
 }
 
 pub enum InGameMsg {
-    Init,
-    Render(Universe),
+    Render(Box<Universe>),
+    Chat(Vec<ChatMessage>),
     CanvasClick(MouseEvent),
+    PointerDown(PointerEvent),
+    PointerMove(PointerEvent),
+    PointerUp(PointerEvent),
+    PointerLeave(PointerEvent),
+    Wheel(WheelEvent),
+    Zoom(f64),
+    ZoomToFit,
+    MinimapClick(MouseEvent),
+    Frame(f64),
+    ToggleAnimations,
+    Key(KeyboardEvent),
+    TogglePalette,
+    SelectAbility(Ability),
+    ToggleHints,
+    ToggleProjection,
+    Resign,
+    OfferDraw,
+    LocalTick,
+    Poll,
 }
 
 impl Component for InGame {
@@ -283,198 +652,1554 @@ impl Component for InGame {
 
     fn create(ctx: &Context<Self>) -> Self {
         let canvas = NodeRef::default();
-        ctx.link().send_message(InGameMsg::Init);
+        let local = ctx.props().local;
+        let mut universe = Universe::new_empty();
+        let mut bot = None;
+        if let Some(local) = local {
+            let seed = now_ms() as u64;
+            universe = Universe::generate(
+                WIDTH_UNIVERSE as usize,
+                HEIGHT_UNIVERSE as usize,
+                N_NEUTRAL_BLOCKS,
+                seed,
+            )
+            .with_settings(GameSettings::default());
+            universe.red_player_connected = true;
+            universe.blue_player_connected = true;
+            if let LocalGame::Bot(difficulty) = local {
+                bot = Some(difficulty.bot(seed));
+            }
+        }
+        // offline games run their own clock, the others follow the server
+        let link = ctx.link().clone();
+        let (tick, interval) = match local {
+            Some(_) => (
+                Closure::<dyn FnMut()>::new(move || link.send_message(InGameMsg::LocalTick)),
+                LOCAL_TICK_MS,
+            ),
+            None => (
+                Closure::<dyn FnMut()>::new(move || link.send_message(InGameMsg::Poll)),
+                POLL_MS,
+            ),
+        };
+        let handle = web_sys::window()
+            .unwrap()
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                tick.as_ref().unchecked_ref(),
+                interval,
+            )
+            .unwrap();
+        if local.is_none() {
+            ctx.link().send_message(InGameMsg::Poll);
+        }
         InGame {
             canvas,
-            universe: Universe::new_empty(),
+            universe,
+            selected: None,
+            first_pick: None,
+            show_hints: false,
+            hints: vec![],
+            hints_for: None,
+            show_projection: false,
+            hover: None,
+            preview: None,
+            touch: false,
+            armed: None,
+            display: DisplaySettings::load(),
+            animation: vec![],
+            animation_start: None,
+            animation_progress: 0.,
+            frame_requested: false,
+            announcement: String::new(),
+            cursor_text: String::new(),
+            drawn: vec![],
+            drawn_size: (0, 0),
+            drawn_view: (0., (0., 0.)),
+            zoom: None,
+            pan: (0., 0.),
+            pointers: vec![],
+            drag_distance: 0.,
+            minimap: NodeRef::default(),
+            wheel_listener: None,
+            bot,
+            last_tick: now_ms(),
+            timer: Some((handle, tick)),
+            polling: false,
+            chat: vec![],
         }
     }
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            InGameMsg::Init => {
-                self.init(ctx);
-                true
-            }
             InGameMsg::Render(uni) => {
-                self.universe = uni;
-                if self.universe.is_finished() {
-                    let navigator = ctx.link().navigator().unwrap();
-                    let cell_numbers = self.universe.get_cell_numbers();
-                    let winner = if cell_numbers.1 > cell_numbers.2 {
-                        "Red"
-                    } else if cell_numbers.2 > cell_numbers.1 {
-                        "Blue"
-                    } else {
-                        "None"
-                    };
-                    ctx.props().app_hook.emit(winner.into());
-                    navigator.push(&Route::VictoryScreen);
-                }
-                self.render_universe();
-                true
+                self.polling = false;
+                self.show(ctx, *uni)
             }
-            InGameMsg::CanvasClick(eve) => {
-                let x = eve.offset_x() as u32;
-                let y = eve.offset_y() as u32;
-                log!("clicked at: {x}, {y}");
-
-                let x_uni = x * WIDTH_UNIVERSE / WIDTH_CANVAS;
-                let y_uni = y * HEIGHT_UNIVERSE / HEIGHT_CANVAS;
-                log!("zelle:  {x_uni}, {y_uni}");
-                let url = "/api/universe/cellpick";
+            InGameMsg::Chat(chat) => {
+                let changed = chat != self.chat;
+                self.chat = chat;
+                changed
+            }
+            InGameMsg::Poll => {
+                if self.polling {
+                    return false;
+                }
+                self.polling = true;
+                let link = ctx.link().clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    Request::post(url)
-                        .header("Content-Type", "application/json")
-                        .body(serde_json::to_string(&(x_uni, y_uni)).unwrap())
+                    let url = "/api/universe/universe";
+                    let response: GameUpdate = Request::get(url)
                         .send()
                         .await
+                        .unwrap()
+                        .json()
+                        .await
                         .unwrap();
+                    link.send_message(InGameMsg::Chat(response.chat));
+                    link.send_message(InGameMsg::Render(Box::new(response.universe)));
                 });
+                false
+            }
+            InGameMsg::LocalTick => {
+                let now = now_ms();
+                let mut next = self.universe.clone();
+                next.advance_clock((now - self.last_tick) / 1000.);
+                self.last_tick = now;
+                if let Some(bot) = self.bot.as_mut() {
+                    let clock = next.get_clock();
+                    if clock.turn == Color::Blue && !clock.picked && !next.is_finished() {
+                        if let Some(coords) = bot.pick(&next, &Color::Blue) {
+                            let _ = next.pick(&Color::Blue, coords);
+                        }
+                        next.bot_estimate = bot.stats().map(|s| s.win_rate);
+                    }
+                }
+                self.show(ctx, next)
+            }
+            InGameMsg::CanvasClick(eve) => {
+                if self.drag_distance > DRAG_THRESHOLD {
+                    return false;
+                }
+                let coords = match self.cell_at(&eve) {
+                    Some(coords) => coords,
+                    None => return false,
+                };
+                if self.touch && self.armed != Some(coords) {
+                    self.armed = Some(coords);
+                    self.hover = Some(coords);
+                    self.update_preview(ctx);
+                    return true;
+                }
+                self.armed = None;
+                self.activate(ctx, coords)
+            }
+            InGameMsg::Key(event) => {
+                let (dx, dy) = match event.key().as_str() {
+                    "ArrowLeft" => (-1, 0),
+                    "ArrowRight" => (1, 0),
+                    "ArrowUp" => (0, -1),
+                    "ArrowDown" => (0, 1),
+                    "Enter" | " " => {
+                        event.prevent_default();
+                        return match self.hover {
+                            Some(coords) => self.activate(ctx, coords),
+                            None => false,
+                        };
+                    }
+                    "Escape" => {
+                        self.selected = None;
+                        self.first_pick = None;
+                        self.update_preview(ctx);
+                        return true;
+                    }
+                    "+" | "=" => {
+                        ctx.link().send_message(InGameMsg::Zoom(ZOOM_STEP));
+                        return false;
+                    }
+                    "-" => {
+                        ctx.link().send_message(InGameMsg::Zoom(1. / ZOOM_STEP));
+                        return false;
+                    }
+                    "0" => {
+                        ctx.link().send_message(InGameMsg::ZoomToFit);
+                        return false;
+                    }
+                    _ => return false,
+                };
+                // keep the page from scrolling
+                event.prevent_default();
+                let (width, height) = (self.universe.width(), self.universe.height());
+                let cursor = match self.hover {
+                    Some((x, y)) => (
+                        (x as i32 + dx).clamp(0, width as i32 - 1) as usize,
+                        (y as i32 + dy).clamp(0, height as i32 - 1) as usize,
+                    ),
+                    None => (width / 2, height / 2),
+                };
+                self.hover = Some(cursor);
+                self.update_preview(ctx);
+                self.cursor_text = self.describe(cursor);
+                self.reveal(cursor);
+                true
+            }
+            InGameMsg::TogglePalette => {
+                self.display.colour_blind = !self.display.colour_blind;
+                self.display.save();
+                // every cell has to be painted again
+                self.drawn.clear();
+                true
+            }
+            InGameMsg::PointerDown(event) => {
+                self.touch = event.pointer_type() != "mouse";
+                if self.pointers.is_empty() {
+                    self.drag_distance = 0.;
+                }
+                let position = (event.offset_x() as f64, event.offset_y() as f64);
+                self.pointers.push((event.pointer_id(), position));
+                // keep getting the moves when the pointer leaves the canvas
+                let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
+                let _ = canvas.set_pointer_capture(event.pointer_id());
+                false
+            }
+            InGameMsg::PointerUp(event) => {
+                self.pointers.retain(|p| p.0 != event.pointer_id());
+                false
+            }
+            InGameMsg::PointerMove(event) => {
+                let position = (event.offset_x() as f64, event.offset_y() as f64);
+                if let Some(i) = self.pointers.iter().position(|p| p.0 == event.pointer_id()) {
+                    let last = std::mem::replace(&mut self.pointers[i].1, position);
+                    return self.drag(last, position);
+                }
+                if event.pointer_type() != "mouse" {
+                    return false;
+                }
+                let hover = self.cell_at(&event);
+                if hover == self.hover {
+                    return false;
+                }
+                self.hover = hover;
+                self.update_preview(ctx);
+                true
+            }
+            InGameMsg::Wheel(event) => {
+                let anchor = (event.offset_x() as f64, event.offset_y() as f64);
+                // some browsers scroll by lines instead of pixels
+                let delta = match event.delta_mode() {
+                    WheelEvent::DOM_DELTA_PIXEL => event.delta_y(),
+                    _ => event.delta_y() * 16.,
+                };
+                self.zoom_at((-delta * 0.002).exp(), anchor);
+                true
+            }
+            InGameMsg::Zoom(factor) => {
+                let (width, height) = self.canvas_size();
+                self.zoom_at(factor, (width as f64 / 2., height as f64 / 2.));
                 true
             }
+            InGameMsg::ZoomToFit => {
+                self.zoom = None;
+                true
+            }
+            InGameMsg::MinimapClick(event) => {
+                // center the view on the clicked spot
+                let scale = self.minimap_scale();
+                let (zoom, _) = self.transform();
+                let (width, height) = self.canvas_size();
+                let pitch = (self.cell_size() + 1) as f64;
+                self.zoom = Some(zoom);
+                self.pan = (
+                    event.offset_x() as f64 / scale * pitch - width as f64 / zoom / 2.,
+                    event.offset_y() as f64 / scale * pitch - height as f64 / zoom / 2.,
+                );
+                self.pan = self.transform().1;
+                true
+            }
+            InGameMsg::PointerLeave(event) => {
+                if event.pointer_type() != "mouse" || self.hover.is_none() {
+                    return false;
+                }
+                self.hover = None;
+                self.update_preview(ctx);
+                true
+            }
+            InGameMsg::Frame(time) => {
+                self.frame_requested = false;
+                let start = *self.animation_start.get_or_insert(time);
+                self.animation_progress = ((time - start) / SPREAD_ANIMATION_MS).min(1.);
+                if self.animation_progress < 1. {
+                    self.request_frame(ctx);
+                } else {
+                    self.animation.clear();
+                }
+                // only the canvas changes, the view stays as it is
+                self.render_universe();
+                false
+            }
+            InGameMsg::ToggleAnimations => {
+                self.display.animations = !self.display.animations;
+                self.display.save();
+                self.animation.clear();
+                true
+            }
+            InGameMsg::SelectAbility(ability) => {
+                self.first_pick = None;
+                if self.selected == Some(ability) {
+                    self.selected = None;
+                } else {
+                    self.selected = Some(ability);
+                }
+                self.update_preview(ctx);
+                true
+            }
+            InGameMsg::ToggleHints => {
+                self.show_hints = !self.show_hints;
+                self.update_hints(ctx);
+                true
+            }
+            InGameMsg::ToggleProjection => {
+                self.show_projection = !self.show_projection;
+                true
+            }
+            InGameMsg::Resign => {
+                if ctx.props().local.is_some() {
+                    let color = self.player(ctx);
+                    self.universe.finish(GameOutcome::Forfeit(color.opponent()));
+                    return true;
+                }
+                wasm_bindgen_futures::spawn_local(async move {
+                    Request::post("/api/universe/resign").send().await.unwrap();
+                });
+                false
+            }
+            InGameMsg::OfferDraw => {
+                if ctx.props().local.is_some() {
+                    // the computer never takes the offer
+                    return self.universe.offer_draw(&self.player(ctx));
+                }
+                wasm_bindgen_futures::spawn_local(async move {
+                    Request::post("/api/universe/draw").send().await.unwrap();
+                });
+                false
+            }
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        let link = ctx.link().clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            let url = "/api/universe/universe";
-            let response = Request::get(url)
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            console::log_1(&JsValue::from(format!("{:?}", response)));
-            link.send_message(InGameMsg::Render(response));
-        });
-        let timer = self.universe.get_timer();
+        let clock = self.universe.get_clock();
+        let clock_settings = &self.universe.get_settings().clock;
+        let time_left = (clock_settings.turn_length - clock.elapsed).max(0.);
+        let banks = match clock_settings.mode {
+            ClockMode::Fixed => html! {},
+            ClockMode::Bank { .. } => html! {
+                <p>{format!("Bank: Red {:.1}s, Blue {:.1}s", clock.red_bank, clock.blue_bank)}</p>
+            },
+        };
+        let goal = self.universe.get_settings().victory_rule.to_string();
         let cell_numbers = self.universe.get_cell_numbers();
+        // spectators have no color to play with
+        let playing = self.player(ctx) != Color::None;
+        let draw_offer = if self.universe.draw_offer == Color::None {
+            html! {}
+        } else if !playing {
+            html! { <p>{format!("{:?} offers a draw", self.universe.draw_offer)}</p> }
+        } else if self.universe.draw_offer == self.player(ctx) {
+            html! { <p>{"You offered a draw"}</p> }
+        } else {
+            html! { <p>{"Your opponent offers a draw"}</p> }
+        };
+        let bot_estimate = match self.universe.bot_estimate {
+            Some(p) if p >= 0.5 => html! {
+                <p>{format!("The computer thinks it is winning ({:.0}%)", p * 100.)}</p>
+            },
+            Some(p) => html! {
+                <p>{format!("The computer thinks it is losing ({:.0}%)", p * 100.)}</p>
+            },
+            None => html! {},
+        };
+        let abilities = self.universe.get_abilities(&self.player(ctx));
+        let ability_buttons = Ability::ALL
+            .iter()
+            .map(|&ability| {
+                let label = if self.selected == Some(ability) {
+                    format!("[{}: {}]", ability, abilities.left(ability))
+                } else {
+                    format!("{}: {}", ability, abilities.left(ability))
+                };
+                html! {
+                    <button disabled={abilities.left(ability) == 0}
+                        onclick={ctx.link().callback(move |_| InGameMsg::SelectAbility(ability))}>
+                        {label}
+                    </button>
+                }
+            })
+            .collect::<Html>();
+        let hints_button = if self.universe.get_settings().hints && playing {
+            html! {
+                <button onclick={ctx.link().callback(|_| InGameMsg::ToggleHints)}>
+                    {if self.show_hints { "Hide hints" } else { "Show hints" }}
+                </button>
+            }
+        } else {
+            html! {}
+        };
+        let projected = if self.show_projection {
+            let (red, blue) = self.universe.projected_score();
+            html! { <p>{format!("Projected: Red {}, Blue {}", red, blue)}</p> }
+        } else {
+            html! {}
+        };
+        let armed = match self.armed {
+            Some(_) => html! { <p>{"Tap the cell again to confirm"}</p> },
+            None => html! {},
+        };
+        let (width_css, height_css) = self.canvas_size();
+        let (width_canvas, height_canvas) = self.canvas_pixels();
+        let minimap = if self.board_fits() {
+            html! {}
+        } else {
+            let scale = self.minimap_scale();
+            let (zoom, (left, top)) = self.transform();
+            let pitch = (self.cell_size() + 1) as f64;
+            // the part of the board on the canvas, in minimap pixels
+            let frame = format!(
+                "position: absolute; pointer-events: none; border: 1px solid {}; \
+                 left: {:.0}px; top: {:.0}px; width: {:.0}px; height: {:.0}px",
+                LEGAL_COLOR,
+                left / pitch * scale,
+                top / pitch * scale,
+                width_css as f64 / zoom / pitch * scale,
+                height_css as f64 / zoom / pitch * scale
+            );
+            let (width, height) = (self.universe.width(), self.universe.height());
+            html! {
+                <div style="position: relative; display: inline-block; overflow: hidden">
+                    <canvas ref={self.minimap.clone()}
+                        width={width.to_string()} height={height.to_string()}
+                        style={format!(
+                            "width: {:.0}px; height: {:.0}px; image-rendering: pixelated",
+                            width as f64 * scale,
+                            height as f64 * scale
+                        )}
+                        aria-hidden="true"
+                        onclick={ctx.link().callback(InGameMsg::MinimapClick)}/>
+                    <div style={frame}></div>
+                </div>
+            }
+        };
+        // nobody to talk to in offline games
+        let chat = if ctx.props().local.is_some() {
+            html! {}
+        } else {
+            html! {
+                <aside style="float: right; width: 20em">
+                    <ChatBox messages={self.chat.clone()} url="/api/universe/chat"/>
+                </aside>
+            }
+        };
+        let board_label = format!(
+            "Game board, {} by {} cells. Arrow keys move the cursor, Enter picks the cell under it, \
+             Escape drops the selected ability and plus, minus and zero zoom.",
+            self.universe.width(),
+            self.universe.height()
+        );
         html! {<>
+            {chat}
             <canvas id="drawing"
-                width = {format!("{WIDTH_CANVAS}")}
-            height = {format!("{HEIGHT_CANVAS}")}
+                width = {format!("{width_canvas}")}
+            height = {format!("{height_canvas}")}
+                style={format!("width: {width_css}px; height: {height_css}px; touch-action: none")}
                 ref={self.canvas.clone()}
-            onclick={ctx.link().callback(|event: web_sys::MouseEvent| InGameMsg::CanvasClick(event))}/>
-                <p>{"Timer: "}{format!("{:.2}", timer)}</p>
+                tabindex="0"
+                role="application"
+                aria-label={board_label}
+            onclick={ctx.link().callback(|event: web_sys::MouseEvent| InGameMsg::CanvasClick(event))}
+            onpointerdown={ctx.link().callback(InGameMsg::PointerDown)}
+            onpointermove={ctx.link().callback(InGameMsg::PointerMove)}
+            onpointerup={ctx.link().callback(InGameMsg::PointerUp)}
+            onpointercancel={ctx.link().callback(InGameMsg::PointerUp)}
+            onpointerleave={ctx.link().callback(InGameMsg::PointerLeave)}
+            onkeydown={ctx.link().callback(InGameMsg::Key)}/>
+                {minimap}
+                <div>
+                    <button onclick={ctx.link().callback(|_| InGameMsg::Zoom(ZOOM_STEP))}>{"Zoom in"}</button>
+                    <button onclick={ctx.link().callback(|_| InGameMsg::Zoom(1. / ZOOM_STEP))}>{"Zoom out"}</button>
+                    <button onclick={ctx.link().callback(|_| InGameMsg::ZoomToFit)}>{"Fit"}</button>
+                </div>
+                <p aria-live="polite">{&self.cursor_text}</p>
+                <p role="status" aria-live="polite">{&self.announcement}</p>
+                {armed}
+                <p>{"Goal: "}{goal}</p>
+                <p>{format!("Turn: {:?}, {:.2}s left", clock.turn, time_left)}</p>
+                {banks}
                 <p>{"Empty Cells: "}{cell_numbers.0}</p>
                 <p>{"Red Cells: "}{cell_numbers.1}</p>
                 <p>{"Blue Cells: "}{cell_numbers.2}</p>
                 <p>{"Neutral Cells: "}{cell_numbers.3}</p>
+                {projected}
+                {bot_estimate}
+                if playing {
+                    <div>{ability_buttons}</div>
+                } else {
+                    <p>{"You are watching this game"}</p>
+                }
+                <button onclick={ctx.link().callback(|_| InGameMsg::ToggleProjection)}>
+                    {if self.show_projection { "Hide projection" } else { "Show projection" }}
+                </button>
+                {hints_button}
+                <button onclick={ctx.link().callback(|_| InGameMsg::ToggleAnimations)}>
+                    {if self.display.animations { "Animations: on" } else { "Animations: off" }}
+                </button>
+                <button onclick={ctx.link().callback(|_| InGameMsg::TogglePalette)}>
+                    {if self.display.colour_blind { "Palette: colour-blind" } else { "Palette: standard" }}
+                </button>
+                {draw_offer}
+                if playing {
+                    <button onclick={ctx.link().callback(|_| InGameMsg::OfferDraw)}>{"Draw"}</button>
+                    <button onclick={ctx.link().callback(|_| InGameMsg::Resign)}>{"Resign"}</button>
+                }
                 </>
         }
     }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if first_render {
+            let link = ctx.link().clone();
+            let listener = Closure::<dyn FnMut(WheelEvent)>::new(move |event: WheelEvent| {
+                event.prevent_default();
+                link.send_message(InGameMsg::Wheel(event));
+            });
+            let mut options = AddEventListenerOptions::new();
+            options.passive(false);
+            let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
+            canvas
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    "wheel",
+                    listener.as_ref().unchecked_ref(),
+                    &options,
+                )
+                .unwrap();
+            self.wheel_listener = Some(listener);
+        }
+        // after the view, so a resized canvas is already in place
+        self.render_universe();
+        self.render_minimap();
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some((handle, _)) = self.timer.take() {
+            web_sys::window()
+                .unwrap()
+                .clear_interval_with_handle(handle);
+        }
+    }
 }
 
 impl InGame {
-    fn init(&self, ctx: &Context<Self>) {
+    fn context(&self) -> CanvasRenderingContext2d {
         let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
-        let cctx: CanvasRenderingContext2d = canvas
+        canvas
             .get_context("2d")
             .unwrap()
             .unwrap()
             .dyn_into()
-            .unwrap();
-
-        cctx.set_fill_style(&JsValue::from("white"));
-        // cctx.set_fill_style(&JsValue::from("rgb(0,79,92)"));
-        cctx.fill_rect(0.0, 0.0, WIDTH_CANVAS.into(), HEIGHT_CANVAS.into());
-        // let _ = cctx.fill_text("hello", 200.0, 200.0);
-        self.draw_grid();
+            .unwrap()
     }
 
-    fn render_universe(&self) {
-        let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
-        let cctx: CanvasRenderingContext2d = canvas
-            .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into()
-            .unwrap();
+    /// Paints the visible cells that look different from the last time. The
+    /// whole canvas is only painted again when it was resized, zoomed or
+    /// panned.
+    fn render_universe(&mut self) {
+        let cctx = self.context();
+        let pixels = self.canvas_pixels();
+        let view = self.transform();
+        let looks = self.cell_looks();
+        if self.drawn_size != pixels || self.drawn_view != view || self.drawn.len() != looks.len() {
+            let ratio = pixel_ratio();
+            let _ = cctx.set_transform(1., 0., 0., 1., 0., 0.);
+            cctx.set_fill_style(&JsValue::from(self.display.theme().grid));
+            cctx.fill_rect(0., 0., pixels.0 as f64, pixels.1 as f64);
+            // from here on everything is drawn in board pixels, the canvas
+            // has one pixel per device pixel
+            let (zoom, (left, top)) = view;
+            let scale = ratio * zoom;
+            let _ = cctx.set_transform(scale, 0., 0., scale, -left * scale, -top * scale);
+            let (width, height) = self.board_size();
+            cctx.set_fill_style(&JsValue::from(self.display.theme().empty));
+            cctx.fill_rect(0., 0., width, height);
+            if self.display.grid {
+                self.draw_grid(&cctx);
+            }
+            self.drawn = vec![None; looks.len()];
+            self.drawn_size = pixels;
+            self.drawn_view = view;
+        }
+        let cell_size = self.cell_size();
+        let ((x_min, y_min), (x_max, y_max)) = self.visible_cells();
+        for (idx, look) in looks.into_iter().enumerate() {
+            let (x, y) = self.universe.get_coords(idx);
+            if x < x_min || x > x_max || y < y_min || y > y_max {
+                continue;
+            }
+            if self.drawn[idx].as_ref() != Some(&look) {
+                let (x, y) = cell_origin(self.universe.get_coords(idx), cell_size);
+                self.draw_cell(&cctx, x, y, cell_size as f64, &look);
+                self.drawn[idx] = Some(look);
+            }
+        }
+    }
 
+    fn cell_looks(&self) -> Vec<CellLook> {
+        let settings = self.universe.get_settings();
         let cells = self.universe.get_cells();
-
-        let fill_rectangles = |cell_style: Cell, cell_color: &str| {
-            cctx.set_fill_style(&JsValue::from(cell_color));
-            for row in 0..WIDTH_UNIVERSE {
-                for col in 0..HEIGHT_UNIVERSE {
-                    if cells[self
-                        .universe
-                        .get_index((row as usize, col as usize))
-                        .unwrap()]
-                        != cell_style
+        let terrain = self.universe.get_terrain();
+        let ages = self.universe.get_ages();
+        let projection = if self.show_projection {
+            self.universe.projection()
+        } else {
+            vec![]
+        };
+        let best_hint = self.hints.iter().cloned().fold(0., f32::max);
+        let ghosts = self.preview.as_deref().unwrap_or(&[]);
+        let mut growing = vec![None; cells.len()];
+        for &(idx, from) in self.animation.iter() {
+            growing[idx] = Some(from);
+        }
+        let grown = (self.animation_progress * 100.) as u8;
+        let hovered = self.hover.and_then(|c| self.universe.get_index(c).ok());
+        let objective = match settings.victory_rule {
+            VictoryRule::Objective(coords) => self.universe.get_index(coords).ok(),
+            _ => None,
+        };
+        (0..cells.len())
+            .map(|idx| {
+                let cell = cells[idx];
+                let fade = match &settings.decay {
+                    // cells that stopped spreading fade out towards their lifespan
+                    Some(decay)
+                        if (cell == Cell::Red || cell == Cell::Blue)
+                            && ages[idx] > decay.spread_age =>
                     {
-                        continue;
+                        (50 * ages[idx].min(decay.lifespan) / decay.lifespan) as u8
                     }
-                    cctx.fill_rect(
-                        (col * (CELL_SIZE + 1) + 1) as f64,
-                        (row * (CELL_SIZE + 1) + 1) as f64,
-                        CELL_SIZE as f64,
-                        CELL_SIZE as f64,
-                    );
+                    _ => 0,
+                };
+                let projected = match projection.get(idx) {
+                    Some(color) if cell == Cell::Empty => color.clone(),
+                    _ => Color::None,
+                };
+                let hint = match self.hints.get(idx) {
+                    Some(&gain) if gain > 0. => (15. + 60. * gain / best_hint) as u8,
+                    _ => 0,
+                };
+                CellLook {
+                    cell,
+                    terrain: terrain[idx],
+                    fade,
+                    projected,
+                    hint,
+                    objective: objective == Some(idx),
+                    hovered: if hovered == Some(idx) {
+                        Some(self.preview.is_some())
+                    } else {
+                        None
+                    },
+                    ghost: ghosts.contains(&idx),
+                    grow: match growing.get(idx) {
+                        Some(Some(from)) => (grown, *from),
+                        _ => (100, (0, 0)),
+                    },
                 }
-            }
-        };
-
-        cctx.begin_path();
-        fill_rectangles(Cell::Empty, EMPTY_COLOR);
-        fill_rectangles(Cell::Red, RED_COLOR);
-        fill_rectangles(Cell::Blue, BLUE_COLOR);
-        fill_rectangles(Cell::Neutral, WALL_COLOR);
-        cctx.stroke();
+            })
+            .collect()
     }
 
-    fn fill_rects(&self, cells: &Vec<Cell>, cctx: &CanvasRenderingContext2d, cell_type: Cell) {
-        for row in 0..WIDTH_UNIVERSE {
-            for col in 0..HEIGHT_UNIVERSE {
-                if cells[self
-                    .universe
-                    .get_index((row as usize, col as usize))
-                    .unwrap()]
-                    != cell_type
-                {
-                    continue;
+    /// Paints one cell from scratch, nothing is drawn outside its square.
+    fn draw_cell(
+        &self,
+        cctx: &CanvasRenderingContext2d,
+        x: f64,
+        y: f64,
+        size: f64,
+        look: &CellLook,
+    ) {
+        let palette = self.display.palette();
+        let theme = self.display.theme();
+        // ghosts only show up on the player's own turn
+        let own_color = if self.universe.get_clock().turn == Color::Red {
+            palette.red
+        } else {
+            palette.blue
+        };
+        let fill = |color: &str, alpha: f64| {
+            cctx.set_global_alpha(alpha);
+            cctx.set_fill_style(&JsValue::from(color));
+            cctx.fill_rect(x, y, size, size);
+            cctx.set_global_alpha(1.);
+        };
+        let color = match look.cell {
+            Cell::Empty => theme.empty,
+            Cell::Red => palette.red,
+            Cell::Blue => palette.blue,
+            Cell::Neutral => theme.wall,
+        };
+        match look.grow {
+            (100, _) => fill(color, 1.),
+            (grown, (dx, dy)) => {
+                // the claimed part reaches in from the side of the claim
+                fill(theme.empty, 1.);
+                let part = size * grown as f64 / 100.;
+                let (w, h) = if (dx, dy) == (0, 0) {
+                    (part, part)
+                } else if dx != 0 {
+                    (part, size)
+                } else {
+                    (size, part)
+                };
+                let left = match dx {
+                    -1 => x,
+                    1 => x + size - w,
+                    _ => x + (size - w) / 2.,
+                };
+                let top = match dy {
+                    -1 => y,
+                    1 => y + size - h,
+                    _ => y + (size - h) / 2.,
+                };
+                cctx.set_fill_style(&JsValue::from(color));
+                cctx.fill_rect(left, top, w, h);
+            }
+        }
+        if palette.patterns && look.grow.0 == 100 {
+            // a stroke for red and a square for blue, so the colors are
+            // told apart without seeing them
+            cctx.set_fill_style(&JsValue::from(theme.pattern));
+            cctx.set_stroke_style(&JsValue::from(theme.pattern));
+            match look.cell {
+                Cell::Red => {
+                    cctx.begin_path();
+                    cctx.set_line_width(2.);
+                    cctx.move_to(x + 3., y + 3.);
+                    cctx.line_to(x + size - 3., y + size - 3.);
+                    cctx.stroke();
+                    cctx.set_line_width(1.);
                 }
-                cctx.fill_rect(
-                    (col * (CELL_SIZE + 1) + 1) as f64,
-                    (row * (CELL_SIZE + 1) + 1) as f64,
-                    CELL_SIZE as f64,
-                    CELL_SIZE as f64,
-                );
+                Cell::Blue => cctx.fill_rect(x + size / 2. - 2., y + size / 2. - 2., 4., 4.),
+                _ => {}
+            }
+        }
+        if look.fade > 0 {
+            fill(theme.empty, look.fade as f64 / 100.);
+        }
+        match look.projected {
+            Color::Red => fill(palette.red, 0.25),
+            Color::Blue => fill(palette.blue, 0.25),
+            Color::None => {}
+        }
+        draw_terrain(cctx, x, y, size, look.terrain);
+        if look.hint > 0 {
+            fill(HINT_COLOR, look.hint as f64 / 100.);
+        }
+        if look.ghost {
+            fill(own_color, 0.4);
+        }
+        if look.objective {
+            cctx.begin_path();
+            cctx.set_stroke_style(&JsValue::from(OBJECTIVE_COLOR));
+            cctx.set_line_width(2.);
+            cctx.stroke_rect(x + 2., y + 2., size - 4., size - 4.);
+            cctx.set_line_width(1.);
+        }
+        match look.hovered {
+            Some(true) => {
+                cctx.begin_path();
+                cctx.set_stroke_style(&JsValue::from(LEGAL_COLOR));
+                cctx.set_line_width(2.);
+                cctx.stroke_rect(x + 1., y + 1., size - 2., size - 2.);
+                cctx.set_line_width(1.);
             }
+            Some(false) => {
+                cctx.begin_path();
+                cctx.set_stroke_style(&JsValue::from(ILLEGAL_COLOR));
+                cctx.set_line_width(2.);
+                cctx.move_to(x + 3., y + 3.);
+                cctx.line_to(x + size - 3., y + size - 3.);
+                cctx.move_to(x + size - 3., y + 3.);
+                cctx.line_to(x + 3., y + size - 3.);
+                cctx.stroke();
+                cctx.set_line_width(1.);
+            }
+            None => {}
         }
     }
 
-    fn draw_grid(&self) {
-        let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
-        let cctx: CanvasRenderingContext2d = canvas
-            .get_context("2d")
-            .unwrap()
+    /// Animates the cells claimed since `old`, each growing in from a
+    /// neighbour that already had its color.
+    fn start_animation(&mut self, ctx: &Context<Self>, old: &Universe) {
+        let (before, after) = (old.get_cells(), self.universe.get_cells());
+        if before.len() != after.len() || old.width() != self.universe.width() {
+            return;
+        }
+        let width = self.universe.width() as i32;
+        let height = self.universe.height() as i32;
+        let claimed: Vec<(usize, (i32, i32))> = (0..after.len())
+            .filter(|&idx| {
+                (after[idx] == Cell::Red || after[idx] == Cell::Blue) && before[idx] != after[idx]
+            })
+            .map(|idx| {
+                let (x, y) = self.universe.get_coords(idx);
+                let from = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .into_iter()
+                    .find(|(dx, dy)| {
+                        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                        nx >= 0
+                            && ny >= 0
+                            && nx < width
+                            && ny < height
+                            && before[(nx + ny * width) as usize] == after[idx]
+                    })
+                    .unwrap_or((0, 0));
+                (idx, from)
+            })
+            .collect();
+        if claimed.is_empty() {
+            return;
+        }
+        // a new snapshot finishes the last animation at once
+        self.animation = claimed;
+        self.animation_start = None;
+        self.animation_progress = 0.;
+        self.request_frame(ctx);
+    }
+
+    fn request_frame(&mut self, ctx: &Context<Self>) {
+        if self.frame_requested {
+            return;
+        }
+        self.frame_requested = true;
+        let link = ctx.link().clone();
+        let callback = Closure::once_into_js(move |time: f64| {
+            link.send_message(InGameMsg::Frame(time));
+        });
+        web_sys::window()
             .unwrap()
-            .dyn_into()
+            .request_animation_frame(callback.unchecked_ref())
             .unwrap();
+    }
 
-        cctx.begin_path();
-        cctx.set_fill_style(&JsValue::from(GRID_COLOR));
-
-        // Vertical lines
-        for i in 0..WIDTH_UNIVERSE {
-            cctx.move_to((i * (CELL_SIZE + 1) + 1) as f64, 0 as f64);
-            cctx.line_to(
-                (i * (CELL_SIZE + 1) + 1) as f64,
-                ((CELL_SIZE + 1) * HEIGHT_UNIVERSE + 1) as f64,
-            );
+    /// Takes in a new state of the universe, from the server or the clock
+    /// of an offline game.
+    fn show(&mut self, ctx: &Context<Self>, uni: Universe) -> bool {
+        let old = std::mem::replace(&mut self.universe, uni);
+        if self.display.animations {
+            self.start_animation(ctx, &old);
         }
-        // Horizontal lines
-        for j in 0..HEIGHT_UNIVERSE {
-            cctx.move_to(0 as f64, (j * (CELL_SIZE + 1) + 1) as f64);
-            cctx.line_to(
-                ((CELL_SIZE + 1) * WIDTH_UNIVERSE + 1) as f64,
-                (j * (CELL_SIZE + 1) + 1) as f64,
-            );
+        let turn = &self.universe.get_clock().turn;
+        if *turn != old.get_clock().turn {
+            let (_, red, blue, _) = self.universe.get_cell_numbers();
+            let whose = match ctx.props().local {
+                Some(LocalGame::HotSeat) => format!("{:?} to move", turn),
+                _ if *turn == self.player(ctx) => "Your turn".to_string(),
+                _ => "Opponent's turn".to_string(),
+            };
+            self.announcement = format!("{}. Red {}, Blue {}.", whose, red, blue);
+        }
+        if let Some(outcome) = self.universe.get_outcome() {
+            if let Some((handle, _)) = self.timer.take() {
+                web_sys::window()
+                    .unwrap()
+                    .clear_interval_with_handle(handle);
+            }
+            let navigator = ctx.link().navigator().unwrap();
+            ctx.props().app_hook.emit(outcome.to_string().into());
+            navigator.push(&Route::VictoryScreen);
+        }
+        self.update_hints(ctx);
+        self.update_preview(ctx);
+        true
+    }
+
+    /// The color playing from this device, in hot-seat games whoever is to
+    /// move.
+    fn player(&self, ctx: &Context<Self>) -> Color {
+        match ctx.props().local {
+            Some(LocalGame::HotSeat) => self.universe.get_clock().turn.clone(),
+            Some(LocalGame::Bot(_)) => Color::Red,
+            None => Color::from_name(&ctx.props().color),
+        }
+    }
+
+    /// Plays what the selected ability does on `coords`, on the server or in
+    /// an offline game.
+    fn activate(&mut self, ctx: &Context<Self>, coords: Coords) -> bool {
+        let action = match self.action_at(coords) {
+            Some(action) => action,
+            None => {
+                self.first_pick = Some(coords);
+                self.update_preview(ctx);
+                return true;
+            }
+        };
+        self.announcement = match self.selected {
+            None => format!("You picked {}, {}.", coords.0, coords.1),
+            Some(ability) => format!("You used {} on {}, {}.", ability, coords.0, coords.1),
+        };
+        self.selected = None;
+        self.first_pick = None;
+        self.preview = None;
+        if ctx.props().local.is_some() {
+            let color = self.player(ctx);
+            let _ = self.universe.act(&color, &action);
+            self.update_hints(ctx);
+            return true;
+        }
+        let url = "/api/universe/action";
+        wasm_bindgen_futures::spawn_local(async move {
+            Request::post(url)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&action).unwrap())
+                .send()
+                .await
+                .unwrap();
+        });
+        true
+    }
+
+    /// What a screen reader says about a cell.
+    fn describe(&self, coords: Coords) -> String {
+        let idx = self.universe.get_index(coords).unwrap();
+        let cell = match self.universe.get_cells()[idx] {
+            Cell::Empty => "empty",
+            Cell::Red => "red",
+            Cell::Blue => "blue",
+            Cell::Neutral => "wall",
+        };
+        let terrain = match self.universe.get_terrain()[idx] {
+            Terrain::Plain => "",
+            Terrain::Fortress => ", fortress",
+            Terrain::Portal(_) => ", portal",
+            Terrain::Fertile => ", fertile",
+            Terrain::OneWay(_) => ", one-way wall",
+        };
+        let allowed = if self.preview.is_some() {
+            ", allowed"
+        } else {
+            ""
+        };
+        format!("{}, {}: {}{}{}", coords.0, coords.1, cell, terrain, allowed)
+    }
+
+    /// The whole board at one pixel per cell, with the colors of the board.
+    fn render_minimap(&self) {
+        let canvas = match self.minimap.cast::<HtmlCanvasElement>() {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        let cctx: CanvasRenderingContext2d = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        let (palette, theme) = (self.display.palette(), self.display.theme());
+        let colors = [theme.empty, palette.red, palette.blue, theme.wall].map(rgb);
+        let mut data = Vec::with_capacity(4 * self.universe.width() * self.universe.height());
+        for cell in self.universe.get_cells() {
+            let color = match cell {
+                Cell::Empty => colors[0],
+                Cell::Red => colors[1],
+                Cell::Blue => colors[2],
+                Cell::Neutral => colors[3],
+            };
+            data.extend_from_slice(&[color[0], color[1], color[2], 255]);
+        }
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&data),
+            self.universe.width() as u32,
+            self.universe.height() as u32,
+        )
+        .unwrap();
+        let _ = cctx.put_image_data(&image, 0., 0.);
+    }
+
+    /// Minimap pixels per cell.
+    fn minimap_scale(&self) -> f64 {
+        let longest = self.universe.width().max(self.universe.height()) as f64;
+        MINIMAP_SIZE as f64 / longest
+    }
+
+    /// Board size in board pixels, before zooming.
+    fn board_size(&self) -> (f64, f64) {
+        let pitch = self.cell_size() + 1;
+        (
+            (pitch * self.universe.width() as u32 + 1) as f64,
+            (pitch * self.universe.height() as u32 + 1) as f64,
+        )
+    }
+
+    /// The zoom and the board pixel at the top left corner of the canvas.
+    /// Boards smaller than the canvas are centered, larger ones can not be
+    /// panned past their edges.
+    fn transform(&self) -> (f64, (f64, f64)) {
+        let (board_width, board_height) = self.board_size();
+        let (width, height) = self.canvas_size();
+        let fit = (width as f64 / board_width)
+            .min(height as f64 / board_height)
+            .min(1.);
+        let zoom = self.zoom.unwrap_or(fit).clamp(fit, MAX_ZOOM);
+        let clamp = |pan: f64, board: f64, canvas: f64| {
+            let seen = canvas / zoom;
+            if seen >= board {
+                (board - seen) / 2.
+            } else {
+                pan.clamp(0., board - seen)
+            }
+        };
+        (
+            zoom,
+            (
+                clamp(self.pan.0, board_width, width as f64),
+                clamp(self.pan.1, board_height, height as f64),
+            ),
+        )
+    }
+
+    /// Whether the whole board is on the canvas.
+    fn board_fits(&self) -> bool {
+        let (zoom, _) = self.transform();
+        let (board_width, board_height) = self.board_size();
+        let (width, height) = self.canvas_size();
+        board_width * zoom <= width as f64 + 0.5 && board_height * zoom <= height as f64 + 0.5
+    }
+
+    /// First and last cell on the canvas in both directions.
+    fn visible_cells(&self) -> (Coords, Coords) {
+        let (zoom, (left, top)) = self.transform();
+        let (width, height) = self.canvas_size();
+        let pitch = (self.cell_size() + 1) as f64;
+        let first = |pan: f64| (pan / pitch).floor().max(0.) as usize;
+        let last = |pan: f64, canvas: f64| ((pan + canvas / zoom) / pitch).max(0.) as usize;
+        (
+            (first(left), first(top)),
+            (last(left, width as f64), last(top, height as f64)),
+        )
+    }
+
+    /// Zooms by `factor`, keeping the board pixel under `anchor` (in canvas
+    /// pixels) where it is.
+    fn zoom_at(&mut self, factor: f64, anchor: (f64, f64)) {
+        let (zoom, (left, top)) = self.transform();
+        let board = (left + anchor.0 / zoom, top + anchor.1 / zoom);
+        self.zoom = Some(zoom * factor);
+        let (zoom, _) = self.transform();
+        self.pan = (board.0 - anchor.0 / zoom, board.1 - anchor.1 / zoom);
+        self.pan = self.transform().1;
+    }
+
+    /// Follows a pressed pointer from `last` to `position`: one pointer pans
+    /// the board, two pinch to zoom.
+    fn drag(&mut self, last: (f64, f64), position: (f64, f64)) -> bool {
+        self.drag_distance += (position.0 - last.0).hypot(position.1 - last.1);
+        if self.drag_distance <= DRAG_THRESHOLD {
+            return false;
+        }
+        match self.pointers[..] {
+            [_] => {
+                let (zoom, (left, top)) = self.transform();
+                self.zoom = Some(zoom);
+                self.pan = (
+                    left - (position.0 - last.0) / zoom,
+                    top - (position.1 - last.1) / zoom,
+                );
+                self.pan = self.transform().1;
+            }
+            [(_, a), (_, b)] => {
+                // the pointer that moved is already at `position`
+                let other = if a == position { b } else { a };
+                let spread = |p: (f64, f64)| (p.0 - other.0).hypot(p.1 - other.1);
+                let middle = |p: (f64, f64)| ((p.0 + other.0) / 2., (p.1 + other.1) / 2.);
+                if spread(last) < 1. {
+                    return false;
+                }
+                let (zoom, (left, top)) = self.transform();
+                let (from, to) = (middle(last), middle(position));
+                let board = (left + from.0 / zoom, top + from.1 / zoom);
+                self.zoom = Some(zoom * spread(position) / spread(last));
+                let (zoom, _) = self.transform();
+                self.pan = (board.0 - to.0 / zoom, board.1 - to.1 / zoom);
+                self.pan = self.transform().1;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Pans just far enough to get `coords` onto the canvas.
+    fn reveal(&mut self, coords: Coords) {
+        let (zoom, (left, top)) = self.transform();
+        let (width, height) = self.canvas_size();
+        let pitch = (self.cell_size() + 1) as f64;
+        let show = |pan: f64, cell: usize, canvas: f64| {
+            let (start, end) = (cell as f64 * pitch, (cell + 1) as f64 * pitch + 1.);
+            if start < pan {
+                start
+            } else if end > pan + canvas / zoom {
+                end - canvas / zoom
+            } else {
+                pan
+            }
+        };
+        let pan = (
+            show(left, coords.0, width as f64),
+            show(top, coords.1, height as f64),
+        );
+        if pan != (left, top) {
+            self.zoom = Some(zoom);
+            self.pan = pan;
+        }
+    }
+
+    /// Cell under the pointer.
+    fn cell_at(&self, event: &MouseEvent) -> Option<Coords> {
+        let (zoom, (left, top)) = self.transform();
+        let x = left + event.offset_x() as f64 / zoom;
+        let y = top + event.offset_y() as f64 / zoom;
+        if x < 0. || y < 0. {
+            return None;
+        }
+        let pitch = (self.cell_size() + 1) as f64;
+        let coords = ((x / pitch) as usize, (y / pitch) as usize);
+        self.universe.get_index(coords).ok().map(|_| coords)
+    }
+
+    /// What a click on `coords` does with the selected ability, `None` when
+    /// it only marks the first cell of a double pick.
+    fn action_at(&self, coords: Coords) -> Option<Action> {
+        Some(match self.selected {
+            None => Action::Pick(coords),
+            Some(Ability::Wall) => Action::Wall(coords),
+            Some(Ability::Clear) => Action::Clear(coords),
+            Some(Ability::Freeze) => Action::Freeze(coords),
+            Some(Ability::DoublePick) => Action::DoublePick(self.first_pick?, coords),
+        })
+    }
+
+    fn update_preview(&mut self, ctx: &Context<Self>) {
+        self.preview = self.hover.and_then(|coords| {
+            // the first cell of a double pick has to be a legal pick itself
+            let action = self.action_at(coords).unwrap_or(Action::Pick(coords));
+            self.universe.preview(&self.player(ctx), &action)
+        });
+    }
+
+    /// Works the hints out again when the board changed since the last time.
+    fn update_hints(&mut self, ctx: &Context<Self>) {
+        if !self.show_hints || !self.universe.get_settings().hints {
+            self.hints.clear();
+            self.hints_for = None;
+            return;
+        }
+        let key = (self.universe.get_tick(), self.universe.get_cell_numbers());
+        if self.hints_for != Some(key) {
+            self.hints = self.universe.hint_gains(&self.player(ctx));
+            self.hints_for = Some(key);
+        }
+    }
+
+    /// Side of a cell in board pixels, before zooming.
+    fn cell_size(&self) -> u32 {
+        self.display.cell_size
+    }
+
+    /// Canvas size in CSS pixels: the whole board, or as much of it as fits
+    /// into the window.
+    fn canvas_size(&self) -> (u32, u32) {
+        let (width, height) = self.board_size();
+        let (room_width, room_height) = web_sys::window()
+            .map(|window| {
+                let inner = |v: Result<JsValue, JsValue>| v.ok().and_then(|v| v.as_f64());
+                (
+                    inner(window.inner_width()).map_or(width, |w| w - 32.),
+                    inner(window.inner_height()).map_or(height, |h| 0.8 * h),
+                )
+            })
+            .unwrap_or((width, height));
+        (
+            width.min(room_width).max(MINIMAP_SIZE as f64) as u32,
+            height.min(room_height).max(MINIMAP_SIZE as f64) as u32,
+        )
+    }
+
+    /// Canvas size in device pixels, so the board stays sharp on HiDPI
+    /// screens.
+    fn canvas_pixels(&self) -> (u32, u32) {
+        let (width, height) = self.canvas_size();
+        let ratio = pixel_ratio();
+        (
+            (width as f64 * ratio).round() as u32,
+            (height as f64 * ratio).round() as u32,
+        )
+    }
+
+    /// One line around every cell, on the pixels the cells leave free.
+    fn draw_grid(&self, cctx: &CanvasRenderingContext2d) {
+        let (width_canvas, height_canvas) = self.board_size();
+        let pitch = self.cell_size() + 1;
+        cctx.begin_path();
+        cctx.set_stroke_style(&JsValue::from(self.display.theme().grid));
+        cctx.set_line_width(1.);
+        // half a pixel in, so each line covers exactly one pixel
+        for i in 0..=self.universe.width() as u32 {
+            let x = (i * pitch) as f64 + 0.5;
+            cctx.move_to(x, 0.);
+            cctx.line_to(x, height_canvas);
+        }
+        for j in 0..=self.universe.height() as u32 {
+            let y = (j * pitch) as f64 + 0.5;
+            cctx.move_to(0., y);
+            cctx.line_to(width_canvas, y);
+        }
+        cctx.stroke();
+    }
+}
+
+fn draw_terrain(cctx: &CanvasRenderingContext2d, x: f64, y: f64, size: f64, terrain: Terrain) {
+    let (cx, cy) = (x + size / 2., y + size / 2.);
+    cctx.begin_path();
+    match terrain {
+        Terrain::Plain => {}
+        Terrain::Fortress => {
+            cctx.set_stroke_style(&JsValue::from(FORTRESS_COLOR));
+            cctx.set_line_width(2.);
+            cctx.stroke_rect(x + 2., y + 2., size - 4., size - 4.);
+            cctx.set_line_width(1.);
+        }
+        Terrain::Portal(_) => {
+            cctx.set_stroke_style(&JsValue::from(PORTAL_COLOR));
+            let _ = cctx.arc(cx, cy, size / 3., 0., std::f64::consts::TAU);
+            cctx.stroke();
+        }
+        Terrain::Fertile => {
+            cctx.set_fill_style(&JsValue::from(FERTILE_COLOR));
+            let _ = cctx.arc(cx, cy, 2., 0., std::f64::consts::TAU);
+            cctx.fill();
+        }
+        Terrain::OneWay(direction) => {
+            // arrow along the direction
+            let (dx, dy) = direction.offset();
+            let (dx, dy) = (dx as f64 * size / 3., dy as f64 * size / 3.);
+            cctx.set_fill_style(&JsValue::from(ONE_WAY_COLOR));
+            cctx.move_to(cx + dx, cy + dy);
+            cctx.line_to(cx - dx - dy, cy - dy + dx);
+            cctx.line_to(cx - dx + dy, cy - dy - dx);
+            cctx.fill();
+        }
+    }
+}
+
+/// Whether the browser asks for as little motion as possible.
+fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media("(prefers-reduced-motion: reduce)").ok())
+        .flatten()
+        .is_some_and(|query| query.matches())
+}
+
+/// `#RRGGBB` as bytes.
+fn rgb(color: &str) -> [u8; 3] {
+    let channel = |i: usize| {
+        color
+            .get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .unwrap_or(0)
+    };
+    [channel(1), channel(3), channel(5)]
+}
+
+/// Device pixels per CSS pixel.
+fn pixel_ratio() -> f64 {
+    web_sys::window().map_or(1., |window| window.device_pixel_ratio())
+}
+
+/// Top left pixel of a cell, x runs to the right and y down like in
+/// `Universe`.
+fn cell_origin(coords: Coords, cell_size: u32) -> (f64, f64) {
+    (
+        (coords.0 as u32 * (cell_size + 1) + 1) as f64,
+        (coords.1 as u32 * (cell_size + 1) + 1) as f64,
+    )
+}
+
+/// Display preferences, kept in the browser's localStorage.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+struct DisplaySettings {
+    theme: String,
+    /// side of a cell in CSS pixels, boards that would not fit the window
+    /// are drawn smaller
+    cell_size: u32,
+    grid: bool,
+    animations: bool,
+    colour_blind: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            theme: LIGHT_THEME.name.into(),
+            cell_size: CELL_SIZE,
+            grid: true,
+            animations: !prefers_reduced_motion(),
+            colour_blind: false,
         }
-        cctx.stroke();
+    }
+}
+
+impl DisplaySettings {
+    const STORAGE_KEY: &'static str = "cherry-display";
+
+    fn load() -> Self {
+        local_storage()
+            .and_then(|storage| storage.get_item(Self::STORAGE_KEY).ok().flatten())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.set_item(Self::STORAGE_KEY, &serde_json::to_string(self).unwrap());
+        }
+    }
+
+    fn theme(&self) -> &'static Theme {
+        THEMES
+            .iter()
+            .find(|theme| theme.name == self.theme)
+            .unwrap_or(&LIGHT_THEME)
+    }
+
+    fn palette(&self) -> &'static Palette {
+        if self.colour_blind {
+            &COLOR_BLIND_PALETTE
+        } else {
+            &STANDARD_PALETTE
+        }
+    }
+}
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[derive(Clone, PartialEq, Properties)]
+struct ChatProps {
+    messages: Vec<ChatMessage>,
+    /// where posts go, the lobby or the universe of the player
+    url: AttrValue,
+}
+
+enum ChatMsg {
+    Input(String),
+    Send,
+    Emote(&'static str),
+    /// the server turned the message down, with its reason
+    Failed(String),
+}
+
+/// History of a chat channel with a field to write in and the emotes.
+struct ChatBox {
+    input: String,
+    error: Option<String>,
+}
+
+impl ChatBox {
+    fn post(&self, ctx: &Context<Self>, post: ChatPost) {
+        let url = ctx.props().url.clone();
+        let link = ctx.link().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let response = Request::post(&url)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&post).unwrap())
+                .send()
+                .await
+                .unwrap();
+            if !response.ok() {
+                link.send_message(ChatMsg::Failed(response.text().await.unwrap_or_default()));
+            }
+        });
+    }
+}
+
+impl Component for ChatBox {
+    type Message = ChatMsg;
+    type Properties = ChatProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        ChatBox {
+            input: String::new(),
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ChatMsg::Input(text) => self.input = text,
+            ChatMsg::Send => {
+                let text = std::mem::take(&mut self.input);
+                if text.trim().is_empty() {
+                    return true;
+                }
+                self.error = None;
+                self.post(ctx, ChatPost { text, emote: false });
+            }
+            ChatMsg::Emote(emote) => {
+                self.error = None;
+                self.post(
+                    ctx,
+                    ChatPost {
+                        text: emote.into(),
+                        emote: true,
+                    },
+                );
+            }
+            ChatMsg::Failed(reason) => self.error = Some(reason),
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let messages = ctx
+            .props()
+            .messages
+            .iter()
+            .map(|message| {
+                if message.emote {
+                    html! { <li><b>{&message.author}</b>{": "}<big>{&message.text}</big></li> }
+                } else {
+                    html! { <li><b>{&message.author}</b>{": "}{&message.text}</li> }
+                }
+            })
+            .collect::<Html>();
+        let emotes = EMOTES
+            .iter()
+            .map(|&emote| {
+                html! {
+                    <button onclick={ctx.link().callback(move |_| ChatMsg::Emote(emote))}>{emote}</button>
+                }
+            })
+            .collect::<Html>();
+        let error = match &self.error {
+            Some(reason) => html! { <p role="alert">{reason}</p> },
+            None => html! {},
+        };
+        let onsubmit = ctx.link().callback(|e: SubmitEvent| {
+            e.prevent_default();
+            ChatMsg::Send
+        });
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            ChatMsg::Input(input.value())
+        });
+        html! {<>
+            <ul aria-live="polite" aria-label="Chat">{messages}</ul>
+            {error}
+            <form onsubmit={onsubmit}>
+                <input type="text" aria-label="Message"
+                    maxlength={CHAT_MAX_LEN.to_string()}
+                    value={self.input.clone()}
+                    oninput={oninput}/>
+                <button type="submit">{"Send"}</button>
+            </form>
+            <div>{emotes}</div>
+        </>}
+    }
+}
+
+pub enum SettingsMsg {
+    Theme(&'static str),
+    CellSize(u32),
+    Grid(bool),
+    Animations(bool),
+    ColourBlind(bool),
+}
+
+/// Page for the display settings, every change is saved right away.
+pub struct SettingsPage {
+    display: DisplaySettings,
+}
+
+impl Component for SettingsPage {
+    type Message = SettingsMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        SettingsPage {
+            display: DisplaySettings::load(),
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            SettingsMsg::Theme(name) => self.display.theme = name.into(),
+            SettingsMsg::CellSize(size) => {
+                self.display.cell_size = size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE)
+            }
+            SettingsMsg::Grid(on) => self.display.grid = on,
+            SettingsMsg::Animations(on) => self.display.animations = on,
+            SettingsMsg::ColourBlind(on) => self.display.colour_blind = on,
+        }
+        self.display.save();
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let input = |e: Event| {
+            e.target()
+                .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+                .unwrap()
+        };
+        let theme_buttons = THEMES
+            .iter()
+            .map(|theme| {
+                let label = if theme.name == self.display.theme().name {
+                    format!("[{}]", theme.label)
+                } else {
+                    theme.label.to_string()
+                };
+                let name = theme.name;
+                html! {
+                    <button onclick={ctx.link().callback(move |_| SettingsMsg::Theme(name))}>
+                        {label}
+                    </button>
+                }
+            })
+            .collect::<Html>();
+        let checkbox = |label: &str, checked: bool, msg: fn(bool) -> SettingsMsg| {
+            html! {
+                <p><label>
+                    <input type="checkbox" checked={checked}
+                        onchange={ctx.link().callback(move |e| msg(input(e).checked()))}/>
+                    {label}
+                </label></p>
+            }
+        };
+        let navigator = ctx.link().navigator().unwrap();
+        html! {<>
+            <p>{"Theme: "}{theme_buttons}</p>
+            <p><label>
+                {format!("Cell size: {}px ", self.display.cell_size)}
+                <input type="range"
+                    min={MIN_CELL_SIZE.to_string()}
+                    max={MAX_CELL_SIZE.to_string()}
+                    value={self.display.cell_size.to_string()}
+                    onchange={ctx.link().callback(move |e| {
+                        SettingsMsg::CellSize(input(e).value().parse().unwrap_or(CELL_SIZE))
+                    })}/>
+            </label></p>
+            {checkbox("Grid", self.display.grid, SettingsMsg::Grid)}
+            {checkbox("Animations", self.display.animations, SettingsMsg::Animations)}
+            {checkbox("Colour-blind palette", self.display.colour_blind, SettingsMsg::ColourBlind)}
+            <button onclick={Callback::from(move |_| navigator.back())}>{"Back"}</button>
+        </>}
     }
 }
 
@@ -483,6 +2208,278 @@ struct VictoryScreenProps {
     winner: String,
 }
 
+/// The private game form as typed, the rules in the same notation as the
+/// server's environment variables.
+#[derive(Clone)]
+struct PrivateForm {
+    width: String,
+    height: String,
+    victory_rule: String,
+    clock: String,
+    turn_length: String,
+    pick_ends_turn: bool,
+    capture: bool,
+    /// `spread_age,lifespan,support`, empty for none
+    decay: String,
+    neighbourhood: String,
+    hints: bool,
+}
+
+impl Default for PrivateForm {
+    fn default() -> Self {
+        let settings = GameSettings::default();
+        PrivateForm {
+            width: WIDTH_UNIVERSE.to_string(),
+            height: HEIGHT_UNIVERSE.to_string(),
+            victory_rule: "territory".into(),
+            clock: "fixed".into(),
+            turn_length: settings.clock.turn_length.to_string(),
+            pick_ends_turn: settings.clock.pick_ends_turn,
+            capture: settings.capture,
+            decay: String::new(),
+            neighbourhood: settings.neighbourhood.to_string(),
+            hints: settings.hints,
+        }
+    }
+}
+
+impl PrivateForm {
+    fn parse(&self) -> Result<PrivateGame, String> {
+        let number = |name: &str, value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("{} has to be a number", name))
+        };
+        let width = number("The width", &self.width)?;
+        let height = number("The height", &self.height)?;
+        // a bare objective sits in the middle of the chosen board
+        let victory_rule = match self.victory_rule.trim() {
+            "objective" => VictoryRule::Objective((width as usize / 2, height as usize / 2)),
+            rule => rule.parse()?,
+        };
+        let clock = ClockSettings {
+            mode: self.clock.trim().parse()?,
+            turn_length: self
+                .turn_length
+                .trim()
+                .parse()
+                .map_err(|_| "The turn length has to be a number".to_string())?,
+            pick_ends_turn: self.pick_ends_turn,
+        };
+        let settings = GameSettings {
+            victory_rule,
+            clock,
+            capture: self.capture,
+            decay: match self.decay.trim() {
+                "" => None,
+                decay => Some(decay.parse()?),
+            },
+            neighbourhood: self.neighbourhood.trim().parse()?,
+            hints: self.hints,
+            ..GameSettings::default()
+        };
+        let private = PrivateGame {
+            width,
+            height,
+            settings,
+        };
+        private.check()?;
+        Ok(private)
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+struct PrivateGameProps {
+    app_hook: Callback<AttrValue>,
+}
+
+enum PrivateMsg {
+    Text(fn(&mut PrivateForm, String), String),
+    Check(fn(&mut PrivateForm, bool), bool),
+    Create,
+    Created(String),
+    Failed(String),
+}
+
+/// Form for the board and rules of a private game, then its invite link
+/// while the creator waits for the opponent.
+struct PrivateGamePage {
+    form: PrivateForm,
+    error: Option<String>,
+    token: Option<String>,
+}
+
+impl Component for PrivateGamePage {
+    type Message = PrivateMsg;
+    type Properties = PrivateGameProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        PrivateGamePage {
+            form: PrivateForm::default(),
+            error: None,
+            token: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PrivateMsg::Text(set, value) => set(&mut self.form, value),
+            PrivateMsg::Check(set, value) => set(&mut self.form, value),
+            PrivateMsg::Create => {
+                let private = match self.form.parse() {
+                    Ok(private) => private,
+                    Err(e) => {
+                        self.error = Some(e);
+                        return true;
+                    }
+                };
+                self.error = None;
+                let link = ctx.link().clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response = Request::post("/api/lobby/private")
+                        .header("Content-Type", "application/json")
+                        .body(serde_json::to_string(&private).unwrap())
+                        .send()
+                        .await
+                        .unwrap();
+                    if response.ok() {
+                        let created: PrivateGameCreated = response.json().await.unwrap();
+                        link.send_message(PrivateMsg::Created(created.token));
+                    } else {
+                        link.send_message(PrivateMsg::Failed(
+                            response.text().await.unwrap_or_default(),
+                        ));
+                    }
+                });
+            }
+            PrivateMsg::Created(token) => {
+                ctx.props().app_hook.emit("red".into());
+                self.token = Some(token);
+            }
+            PrivateMsg::Failed(e) => self.error = Some(e),
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if let Some(token) = &self.token {
+            let origin = web_sys::window()
+                .and_then(|window| window.location().origin().ok())
+                .unwrap_or_default();
+            let invite = format!("{}/invite/{}", origin, token);
+            return html! {<>
+                <p>{"Send this link to your opponent, only they can join:"}</p>
+                <p><input type="text" readonly=true value={invite} size="60"/></p>
+                <PreGame color="red"/>
+            </>};
+        }
+        let input = |e: Event| {
+            e.target()
+                .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+                .unwrap()
+        };
+        let text = |label: &str, value: &str, set: fn(&mut PrivateForm, String)| {
+            html! {
+                <p><label>
+                    {label}{" "}
+                    <input type="text" value={value.to_string()}
+                        onchange={ctx.link().callback(move |e| PrivateMsg::Text(set, input(e).value()))}/>
+                </label></p>
+            }
+        };
+        let checkbox = |label: &str, checked: bool, set: fn(&mut PrivateForm, bool)| {
+            html! {
+                <p><label>
+                    <input type="checkbox" checked={checked}
+                        onchange={ctx.link().callback(move |e| PrivateMsg::Check(set, input(e).checked()))}/>
+                    {label}
+                </label></p>
+            }
+        };
+        let form = &self.form;
+        let error = match &self.error {
+            Some(e) => html! { <p role="alert">{e}</p> },
+            None => html! {},
+        };
+        html! {<>
+            <p>{"Private games are only open to whoever you send the invite link to."}</p>
+            {text("Width", &form.width, |f, v| f.width = v)}
+            {text("Height", &form.height, |f, v| f.height = v)}
+            {text("Victory rule (territory, share:60, edges, objective, score:200)", &form.victory_rule, |f, v| f.victory_rule = v)}
+            {text("Clock (fixed, bank:60 or bank:60+2)", &form.clock, |f, v| f.clock = v)}
+            {text("Turn length in seconds", &form.turn_length, |f, v| f.turn_length = v)}
+            {checkbox("A pick ends the turn", form.pick_ends_turn, |f, v| f.pick_ends_turn = v)}
+            {checkbox("Enclosed groups are captured", form.capture, |f, v| f.capture = v)}
+            {text("Decay (spread age, lifespan, support, e.g. 4,16,2; empty for none)", &form.decay, |f, v| f.decay = v)}
+            {text("Neighbourhood (von-neumann or moore)", &form.neighbourhood, |f, v| f.neighbourhood = v)}
+            {checkbox("Hints allowed", form.hints, |f, v| f.hints = v)}
+            {error}
+            <button onclick={ctx.link().callback(|_| PrivateMsg::Create)}>{"Create private game"}</button>
+        </>}
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+struct InviteProps {
+    token: String,
+    app_hook: Callback<AttrValue>,
+}
+
+enum InviteMsg {
+    Seated(String),
+    Failed(String),
+}
+
+/// Where invite links lead: takes the second seat of the private game.
+struct InviteLanding {
+    error: Option<String>,
+}
+
+impl Component for InviteLanding {
+    type Message = InviteMsg;
+    type Properties = InviteProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let url = format!("/api/user/invite/{}", ctx.props().token);
+        let link = ctx.link().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let response = Request::post(&url).send().await.unwrap();
+            if response.ok() {
+                let color: ColorSender = response.json().await.unwrap();
+                link.send_message(InviteMsg::Seated(color.value));
+            } else {
+                link.send_message(InviteMsg::Failed(response.text().await.unwrap_or_default()));
+            }
+        });
+        InviteLanding { error: None }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            InviteMsg::Seated(color) => {
+                ctx.props().app_hook.emit(color.into());
+                ctx.link().navigator().unwrap().push(&Route::PreGame);
+                false
+            }
+            InviteMsg::Failed(e) => {
+                self.error = Some(e);
+                true
+            }
+        }
+    }
+
+    fn view(&self, _ctx: &Context<Self>) -> Html {
+        match &self.error {
+            Some(e) => html! {<>
+                <p>{e}</p>
+                <p><Link<Route> to={Route::Home}>{"Back to the start page"}</Link<Route>></p>
+            </>},
+            None => html! { <p>{"Joining the game..."}</p> },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Routable)]
 enum Route {
     #[at("/")]
@@ -495,23 +2492,36 @@ enum Route {
     InGame,
     #[at("/victory")]
     VictoryScreen,
+    #[at("/settings")]
+    Settings,
+    #[at("/local")]
+    LocalHotSeat,
+    #[at("/local/:difficulty")]
+    LocalBot { difficulty: String },
+    #[at("/private")]
+    PrivateGame,
+    #[at("/invite/:token")]
+    Invite { token: String },
 }
 
 pub enum AppMsg {
     UserName(AttrValue),
+    Color(AttrValue),
     InGame(AttrValue),
 }
 
 struct AppState {
     user_name: String,
-    winner: String,
+    color: String,
+    outcome: String,
 }
 
 impl AppState {
     fn new() -> Self {
         AppState {
             user_name: "".into(),
-            winner: "".into(),
+            color: "".into(),
+            outcome: "".into(),
         }
     }
 }
@@ -535,8 +2545,11 @@ impl Component for App {
             AppMsg::UserName(val) => {
                 self.app_state.user_name = val.as_str().into();
             }
+            AppMsg::Color(val) => {
+                self.app_state.color = val.as_str().into();
+            }
             AppMsg::InGame(val) => {
-                self.app_state.winner = val.as_str().into();
+                self.app_state.outcome = val.as_str().into();
             }
         }
         true
@@ -544,9 +2557,11 @@ impl Component for App {
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let app_hook = ctx.link().callback(AppMsg::UserName);
+        let app_hook_au = ctx.link().callback(AppMsg::Color);
         let app_hook_game = ctx.link().callback(AppMsg::InGame);
         let player_name = self.app_state.user_name.clone();
-        let winner_name = self.app_state.winner.clone();
+        let color = self.app_state.color.clone();
+        let outcome = self.app_state.outcome.clone();
         let switch = move |routes: Route| -> Html {
             match routes {
                 Route::Home => {
@@ -555,9 +2570,29 @@ impl Component for App {
                 Route::ActiveUsers => html! {
                     <ActiveUsers player_name={player_name.clone()} app_hook = {app_hook_au.clone()} />
                 },
-                Route::InGame => html! { <InGame app_hook={app_hook_game.clone()}/>},
+                Route::PreGame => html! { <PreGame color={color.clone()} /> },
+                Route::InGame => {
+                    html! { <InGame app_hook={app_hook_game.clone()} color={color.clone()}/>}
+                }
                 Route::VictoryScreen => {
-                    html! { <VictoryScreen winner={winner_name.clone()}/> }
+                    html! { <VictoryScreen outcome={outcome.clone()}/> }
+                }
+                Route::Settings => html! { <SettingsPage /> },
+                Route::PrivateGame => html! {
+                    <PrivateGamePage app_hook={app_hook_au.clone()} />
+                },
+                Route::Invite { token } => html! {
+                    <InviteLanding token={token} app_hook={app_hook_au.clone()} />
+                },
+                Route::LocalHotSeat => html! {
+                    <InGame app_hook={app_hook_game.clone()} color="" local={LocalGame::HotSeat}/>
+                },
+                Route::LocalBot { difficulty } => {
+                    let difficulty = difficulty.parse().unwrap_or(Difficulty::Easy);
+                    html! {
+                        <InGame app_hook={app_hook_game.clone()} color=""
+                            local={LocalGame::Bot(difficulty)}/>
+                    }
                 }
             }
         };